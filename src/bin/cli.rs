@@ -1,4 +1,9 @@
-use engine::{engine::Engine, mdl, utils::io};
+use engine::{
+    color::{self, SCENE_LINEAR},
+    engine::Engine,
+    mdl,
+    utils::{io, Rect, Vec2I},
+};
 use std::path::Path;
 
 fn main() -> Result<(), String> {
@@ -23,6 +28,60 @@ fn main() -> Result<(), String> {
                 .takes_value(true)
                 .help("Sets the output image file"),
         )
+        .arg(
+            ::clap::Arg::with_name("colorspace")
+                .long("colorspace")
+                .value_name("NAME")
+                .takes_value(true)
+                .help("Sets the colorspace of the output image file"),
+        )
+        .arg(
+            ::clap::Arg::with_name("color_config")
+                .long("color-config")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Sets the color configuration file"),
+        )
+        .arg(
+            ::clap::Arg::with_name("region")
+                .long("region")
+                .value_name("X,Y,WIDTH,HEIGHT")
+                .takes_value(true)
+                .help("Renders only part of the comp"),
+        )
+        .arg(
+            ::clap::Arg::with_name("frame")
+                .long("frame")
+                .value_name("FRAME")
+                .takes_value(true)
+                .help("Sets the frame to render"),
+        )
+        .arg(
+            ::clap::Arg::with_name("memory")
+                .long("memory")
+                .value_name("MEGABYTES")
+                .takes_value(true)
+                .help("Renders in tiles to stay within a memory budget"),
+        )
+        .arg(
+            ::clap::Arg::with_name("cache")
+                .long("cache")
+                .value_name("MEGABYTES")
+                .takes_value(true)
+                .help("Limits the memory held by cached node results"),
+        )
+        .arg(
+            ::clap::Arg::with_name("cache_dir")
+                .long("cache-dir")
+                .value_name("DIR")
+                .takes_value(true)
+                .help("Keeps node results on disk to reuse in later renders"),
+        )
+        .arg(
+            ::clap::Arg::with_name("stats")
+                .long("stats")
+                .help("Prints cache statistics after rendering or at the end of a session"),
+        )
         .arg(
             ::clap::Arg::with_name("interactive")
                 .short("i")
@@ -33,35 +92,116 @@ fn main() -> Result<(), String> {
 
     let parser = mdl::Parser::default();
     let mut engine = Engine::new();
+    if let Some(path) = matches.value_of("color_config") {
+        engine.colors = color::Config::load(Path::new(path))?;
+    }
+    if let Some(cache) = matches.value_of("cache") {
+        engine.cache.set_budget(parse_megabytes(cache)? << 20);
+    }
+    if let Some(dir) = matches.value_of("cache_dir") {
+        engine.cache.persist(Path::new(dir))?;
+    }
+    let stats = matches.is_present("stats");
     match matches.value_of("comp_file") {
         Some(comp) => {
             let src = std::fs::read_to_string(comp)
                 .map_err(|_| "Could not load comp file".to_string())?;
             for line in src.lines() {
                 match parser.parse(line) {
-                    Ok(statement) => mdl::apply(&mut engine, &statement)?,
+                    Ok(statement) => {
+                        mdl::apply(&mut engine, &statement)?;
+                    }
                     Err(e) => print!("{}", e),
                 }
             }
-            let comp = engine.render()?;
-            let out = matches.value_of("output").unwrap_or("data/merge.png");
-            io::save(Path::new(out), comp)
+            if let Some(frame) = matches.value_of("frame") {
+                engine.frame = frame
+                    .parse::<isize>()
+                    .map_err(|_| format!("Invalid frame: {}", frame))?;
+            }
+            let colorspace = matches.value_of("colorspace").unwrap_or("srgb");
+            let processor = engine.colors.processor(SCENE_LINEAR, colorspace)?;
+            let out = Path::new(matches.value_of("output").unwrap_or("data/merge.png"));
+            let region = matches.value_of("region").map(parse_region).transpose()?;
+
+            if let (Some(memory), None) = (matches.value_of("memory"), region) {
+                for name in engine.untiled()? {
+                    println!(
+                        "{} needs all of its input, which may take more than the memory budget",
+                        name
+                    );
+                }
+                let mut stream = None;
+                engine.render_tiled(parse_megabytes(memory)? << 20, |frame, tile| {
+                    let stream = match stream.as_mut() {
+                        Some(stream) => stream,
+                        None => stream.insert(io::stream(out, frame, tile.pixel_aspect())?),
+                    };
+                    let mut tile = tile.clone();
+                    processor.apply(&mut tile);
+                    stream.write(&tile)
+                })?;
+                if stats {
+                    println!("Cache: {}", engine.cache.stats());
+                }
+                return match stream {
+                    Some(stream) => stream.finish(),
+                    None => Err("Nothing was rendered".to_string()),
+                };
+            }
+
+            let mut comp = match region {
+                Some(region) => {
+                    let mut comp = engine.render_region(Some(region))?.reframed(region);
+                    comp.set_display_window(region);
+                    comp
+                }
+                None => engine.render()?.clone(),
+            };
+            if stats {
+                println!("Cache: {}", engine.cache.stats());
+            }
+            processor.apply(&mut comp);
+            io::save(out, &comp)
         }
         None => {
             let mut line = String::new();
-            while let Ok(_) = std::io::stdin().read_line(&mut line) {
+            // Reading nothing means the input has ended
+            while let Ok(1..) = std::io::stdin().read_line(&mut line) {
                 match parser.parse(&line) {
-                    Ok(statement) => {
-                        if let Err(e) = mdl::apply(&mut engine, &statement) {
-                            println!("{}", e);
-                        }
-                    }
+                    Ok(statement) => match mdl::apply(&mut engine, &statement) {
+                        Ok(Some(message)) => println!("{}", message),
+                        Ok(None) => (),
+                        Err(e) => println!("{}", e),
+                    },
                     Err(e) => print!("{}", e),
                 };
                 println!();
                 line.clear();
             }
+            if stats {
+                println!("Cache: {}", engine.cache.stats());
+            }
             Ok(())
         }
     }
 }
+
+fn parse_megabytes(src: &str) -> Result<usize, String> {
+    src.parse::<usize>()
+        .map_err(|_| format!("Invalid memory budget: {}", src))
+}
+
+fn parse_region(src: &str) -> Result<Rect, String> {
+    let values = src
+        .split(',')
+        .map(|value| value.trim().parse::<isize>().ok())
+        .collect::<Option<Vec<_>>>();
+    match values.as_deref() {
+        Some([x, y, width, height]) => {
+            let min = Vec2I::new(*x, *y);
+            Ok(Rect::new(min, min + Vec2I::new(*width, *height)))
+        }
+        _ => Err(format!("Invalid region: {}", src)),
+    }
+}
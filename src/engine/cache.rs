@@ -0,0 +1,295 @@
+use crate::{image::Image, utils::Rect};
+use std::{
+    collections::HashMap,
+    fmt, fs, mem,
+    path::{Path, PathBuf},
+};
+
+mod disk;
+
+// Hashes everything that goes into a node's output
+pub type Key = u64;
+
+// Goes into every key, so bumping it when a plugin changes
+// what it renders keeps old outputs on disk from being used
+pub const KEY_VERSION: u32 = 1;
+
+// Node outputs kept between renders. Once they take more than the
+// budget in bytes, those used least recently are dropped first.
+// With a directory set, outputs are also written there so they
+// can be read back by later sessions.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    budget: usize,
+    entries: HashMap<Key, Entry>,
+    clock: u64,
+    stats: Stats,
+    dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    image: Image,
+    // The region rendered, or None for all of it
+    covered: Option<Rect>,
+    used: u64,
+    bytes: usize,
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Stats {
+    pub hits: usize,
+    // Hits read back from disk
+    pub loads: usize,
+    pub misses: usize,
+    pub evictions: usize,
+    pub entries: usize,
+    pub bytes: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} hits ({} from disk), {} misses, {} evictions, {} entries, {:.1} MB",
+            self.hits,
+            self.loads,
+            self.misses,
+            self.evictions,
+            self.entries,
+            self.bytes as f32 / (1 << 20) as f32,
+        )
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Self::new(usize::MAX)
+    }
+}
+
+impl Cache {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            entries: HashMap::new(),
+            clock: 0,
+            stats: Stats::default(),
+            dir: None,
+        }
+    }
+
+    // Keeps outputs in the directory as well, creating it if needed
+    pub fn persist(&mut self, dir: &Path) -> Result<(), String> {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        self.dir = Some(dir.to_path_buf());
+        Ok(())
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    // Takes effect at the next eviction
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            entries: self.entries.len(),
+            ..self.stats
+        }
+    }
+
+    pub fn get(&self, key: Key) -> Option<&Image> {
+        self.entries.get(&key).map(|entry| &entry.image)
+    }
+
+    // Whether the region is held for the key, counting
+    // the outcome toward the stats. Outputs missing from
+    // memory are looked for on disk.
+    pub fn lookup(&mut self, key: Key, region: Option<Rect>) -> bool {
+        if !self.entries.contains_key(&key) {
+            let read = self
+                .dir
+                .as_ref()
+                .and_then(|dir| disk::read(&path(dir, key)).ok());
+            if let Some((image, covered)) = read {
+                if covers(covered, region) {
+                    self.stats.loads += 1;
+                    self.store(key, image, covered);
+                }
+            }
+        }
+        let found = match self.entries.get(&key) {
+            Some(entry) => covers(entry.covered, region),
+            None => false,
+        };
+        if found {
+            self.stats.hits += 1;
+            self.touch(key);
+        } else {
+            self.stats.misses += 1;
+        }
+        found
+    }
+
+    // Marks an entry as recently used
+    pub fn touch(&mut self, key: Key) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.used = self.clock;
+        }
+    }
+
+    pub fn insert(&mut self, key: Key, image: Image, covered: Option<Rect>) -> Result<(), String> {
+        if let Some(dir) = &self.dir {
+            disk::write(&path(dir, key), &image, covered)?;
+        }
+        self.store(key, image, covered);
+        Ok(())
+    }
+
+    fn store(&mut self, key: Key, image: Image, covered: Option<Rect>) {
+        self.remove(key);
+        self.clock += 1;
+        let bytes = bytes(&image);
+        self.stats.bytes += bytes;
+        let entry = Entry {
+            image,
+            covered,
+            used: self.clock,
+            bytes,
+        };
+        self.entries.insert(key, entry);
+    }
+
+    pub fn remove(&mut self, key: Key) {
+        if let Some(entry) = self.entries.remove(&key) {
+            self.stats.bytes -= entry.bytes;
+        }
+    }
+
+    // Takes over entries from another cache that cover more than
+    // those held here, leaving eviction to the next call to evict
+    pub fn merge(&mut self, other: Cache) {
+        for (key, entry) in other.entries {
+            let wanted = match self.entries.get(&key) {
+                Some(held) => covers(entry.covered, held.covered),
+                None => true,
+            };
+            if wanted {
+                self.store(key, entry.image, entry.covered);
+            }
+        }
+    }
+
+    // Only empties memory, anything on disk stays
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.stats.bytes = 0;
+    }
+
+    // Drops the least recently used entries until the rest fit in
+    // the budget. Pinned entries stay regardless, so the total may
+    // still exceed the budget if they alone are larger.
+    pub fn evict(&mut self, pinned: impl Fn(Key) -> bool) {
+        while self.stats.bytes > self.budget {
+            let oldest = self
+                .entries
+                .iter()
+                .filter(|(key, _)| !pinned(**key))
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| *key);
+            match oldest {
+                Some(key) => {
+                    self.remove(key);
+                    self.stats.evictions += 1;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+// Whether a rendered region holds all of the one asked for
+fn covers(covered: Option<Rect>, region: Option<Rect>) -> bool {
+    match (covered, region) {
+        (None, _) => true,
+        (Some(done), Some(region)) => done.intersect(region) == region,
+        _ => false,
+    }
+}
+
+fn path(dir: &Path, key: Key) -> PathBuf {
+    dir.join(format!("{:016x}.cache", key))
+}
+
+fn bytes(image: &Image) -> usize {
+    let elements: usize = image
+        .channels()
+        .map(|channel| channel.size().x * channel.size().y)
+        .sum();
+    elements * mem::size_of::<f32>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{Vec2I, Vec2U};
+    use std::{env, process};
+
+    fn image() -> Image {
+        Image::from_desc(crate::image::Desc::new(Vec2U::new(4, 4), 1))
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        // Room for two images of 64 bytes
+        let mut cache = Cache::new(128);
+        for key in 1..=3 {
+            cache.insert(key, image(), None).unwrap();
+        }
+        cache.touch(1);
+        cache.evict(|key| key == 2);
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(2).is_some());
+        assert!(cache.get(3).is_none());
+        assert_eq!(cache.stats().evictions, 1);
+        assert_eq!(cache.stats().bytes, 128);
+    }
+
+    #[test]
+    fn lookup_counts_regions_covered() {
+        let region = Rect::new(Vec2I::new(0, 0), Vec2I::new(2, 2));
+        let inside = Rect::new(Vec2I::new(1, 1), Vec2I::new(2, 2));
+        let mut cache = Cache::default();
+        cache.insert(1, image(), Some(region)).unwrap();
+        assert!(cache.lookup(1, Some(inside)));
+        assert!(!cache.lookup(1, None));
+        assert!(!cache.lookup(2, Some(inside)));
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+    }
+
+    #[test]
+    fn disk_round_trip() {
+        let dir = env::temp_dir().join(format!("madeline-cache-{}", process::id()));
+        let mut cache = Cache::default();
+        cache.persist(&dir).unwrap();
+        cache.insert(1, image(), None).unwrap();
+
+        // A later session finds the output on disk
+        let mut cache = Cache::default();
+        cache.persist(&dir).unwrap();
+        assert!(cache.lookup(1, None));
+        assert_eq!(cache.get(1).unwrap().size(), Vec2U::new(4, 4));
+        assert_eq!(cache.stats().loads, 1);
+
+        // Damaged files are misses rather than errors
+        fs::write(path(&dir, 2), b"MDLC").unwrap();
+        assert!(!cache.lookup(2, None));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
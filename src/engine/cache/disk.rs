@@ -0,0 +1,193 @@
+use crate::{
+    image::{ChannelBuilder, Image},
+    utils::{Rect, Vec2I},
+};
+use std::{
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+    mem,
+    path::Path,
+};
+
+const MAGIC: &[u8; 4] = b"MDLC";
+const VERSION: u32 = 2;
+
+// Little endian throughout. Files are written under another name
+// and renamed when complete, so that one cut short by a crash is
+// never mistaken for a finished one.
+pub fn write(path: &Path, image: &Image, covered: Option<Rect>) -> Result<(), String> {
+    let partial = path.with_extension("partial");
+    let file = File::create(&partial).map_err(|e| format!("{}: {}", partial.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    write_image(&mut writer, image, covered)
+        .and_then(|_| writer.flush())
+        .map_err(|e| format!("{}: {}", partial.display(), e))?;
+    fs::rename(&partial, path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn write_image<W: Write>(w: &mut W, image: &Image, covered: Option<Rect>) -> std::io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(&[covered.is_some() as u8])?;
+    write_rect(w, covered.unwrap_or_default())?;
+    w.write_all(&[image.is_premultiplied() as u8])?;
+    write_rect(w, image.data_window())?;
+    write_rect(w, image.display_window())?;
+    w.write_all(&image.pixel_aspect().to_le_bytes())?;
+    w.write_all(&(image.channel_count() as u32).to_le_bytes())?;
+    for (name, channel) in image.names().zip(image.channels()) {
+        w.write_all(&(name.len() as u32).to_le_bytes())?;
+        w.write_all(name.as_bytes())?;
+        for element in channel.elements() {
+            w.write_all(&element.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn write_rect<W: Write>(w: &mut W, rect: Rect) -> std::io::Result<()> {
+    for value in &[rect.min.x, rect.min.y, rect.max.x, rect.max.y] {
+        w.write_all(&(*value as i64).to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn read(path: &Path) -> Result<(Image, Option<Rect>), String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    read_image(&mut BufReader::new(file)).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn read_image<R: Read>(r: &mut R) -> Result<(Image, Option<Rect>), String> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic).map_err(|e| format!("{}", e))?;
+    if &magic != MAGIC || read_u32(r)? != VERSION {
+        return Err("Not a cache file of this version".to_string());
+    }
+    let has_covered = read_u8(r)? != 0;
+    let covered = read_rect(r)?;
+    let premultiplied = read_u8(r)? != 0;
+    let data = read_rect(r)?;
+    let display = read_rect(r)?;
+    let pixel_aspect = f32::from_bits(read_u32(r)?);
+    let count = read_u32(r)? as usize;
+    // Images always have channels, and one
+    // without would have no size to go by
+    if count == 0 {
+        return Err("Cache file has no channels".to_string());
+    }
+
+    let size = data.size();
+    let mut channels = Vec::with_capacity(count);
+    for _ in 0..count {
+        let len = read_u32(r)? as usize;
+        let mut name = vec![0; len];
+        r.read_exact(&mut name).map_err(|e| format!("{}", e))?;
+        let name = String::from_utf8(name).map_err(|e| format!("{}", e))?;
+        let mut bytes = vec![0; size.x * size.y * mem::size_of::<f32>()];
+        r.read_exact(&mut bytes).map_err(|e| format!("{}", e))?;
+        let channel = bytes
+            .chunks_exact(mem::size_of::<f32>())
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect::<ChannelBuilder>()
+            .build(size);
+        channels.push((name, channel));
+    }
+
+    let mut image = channels.into_iter().collect::<Image>();
+    image.set_premultiplied(premultiplied);
+    image.set_data_origin(data.min);
+    image.set_display_window(display);
+    image.set_pixel_aspect(pixel_aspect);
+    Ok((image, if has_covered { Some(covered) } else { None }))
+}
+
+fn read_rect<R: Read>(r: &mut R) -> Result<Rect, String> {
+    let mut values = [0; 4];
+    for value in values.iter_mut() {
+        let mut bytes = [0; 8];
+        r.read_exact(&mut bytes).map_err(|e| format!("{}", e))?;
+        *value = i64::from_le_bytes(bytes) as isize;
+    }
+    Ok(Rect::new(
+        Vec2I::new(values[0], values[1]),
+        Vec2I::new(values[2], values[3]),
+    ))
+}
+
+fn read_u32<R: Read>(r: &mut R) -> Result<u32, String> {
+    let mut bytes = [0; 4];
+    r.read_exact(&mut bytes).map_err(|e| format!("{}", e))?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u8<R: Read>(r: &mut R) -> Result<u8, String> {
+    let mut bytes = [0; 1];
+    r.read_exact(&mut bytes).map_err(|e| format!("{}", e))?;
+    Ok(bytes[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Channel;
+    use crate::utils::Vec2U;
+
+    fn image() -> Image {
+        let size = Vec2U::new(3, 2);
+        let mut image = vec![
+            ("rgba.red".to_string(), Channel::filled(size, 0.25)),
+            ("depth.Z".to_string(), Channel::filled(size, -4.0)),
+        ]
+        .into_iter()
+        .collect::<Image>();
+        image[0][4] = 1.5;
+        image.set_premultiplied(true);
+        image.set_data_origin(Vec2I::new(-1, 2));
+        image.set_display_window(Rect::new(Vec2I::new(0, 0), Vec2I::new(4, 4)));
+        image.set_pixel_aspect(2.0);
+        image
+    }
+
+    fn written(image: &Image, covered: Option<Rect>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        write_image(&mut bytes, image, covered).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let image = image();
+        let covered = Some(Rect::new(Vec2I::new(0, 1), Vec2I::new(2, 3)));
+        for covered in &[None, covered] {
+            let bytes = written(&image, *covered);
+            let (read, read_covered) = read_image(&mut bytes.as_slice()).unwrap();
+            assert_eq!(read_covered, *covered);
+            assert_eq!(read.names().collect::<Vec<_>>(), ["rgba.red", "depth.Z"]);
+            assert_eq!(read.data_window(), image.data_window());
+            assert_eq!(read.display_window(), image.display_window());
+            assert_eq!(read.pixel_aspect(), 2.0);
+            assert!(read.is_premultiplied());
+            for (a, b) in read.channels().zip(image.channels()) {
+                assert!(a.elements().eq(b.elements()));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_files() {
+        let bytes = written(&image(), None);
+        for len in &[0, 3, 20, bytes.len() - 1] {
+            assert!(read_image(&mut &bytes[..*len]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_files_without_channels() {
+        let mut bytes = written(&image(), None);
+        // Cut the channels off and zero their count
+        let count = 4 + 4 + 1 + 32 + 1 + 32 + 32 + 4;
+        bytes.truncate(count + 4);
+        bytes[count..].copy_from_slice(&0u32.to_le_bytes());
+        assert!(read_image(&mut bytes.as_slice()).is_err());
+    }
+}
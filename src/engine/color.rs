@@ -0,0 +1,114 @@
+mod config;
+pub use config::*;
+
+mod transform;
+pub use transform::*;
+
+pub mod lut;
+
+// Rec.709 constants carried to enough precision that
+// both segments of the curve meet exactly
+const REC709_ALPHA: f32 = 1.099_296_8;
+const REC709_BETA: f32 = 0.018_053_97;
+
+// Kodak Cineon printing density conventions
+const CINEON_BLACK: f32 = 95.0;
+const CINEON_WHITE: f32 = 685.0;
+const CINEON_GAMMA: f32 = 0.6;
+const CINEON_DENSITY: f32 = 0.002;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Transfer {
+    Linear,
+    Srgb,
+    Rec709,
+    Gamma22,
+    Gamma24,
+    Cineon,
+}
+
+pub const TRANSFERS: [(&str, Transfer); 6] = [
+    ("linear", Transfer::Linear),
+    ("srgb", Transfer::Srgb),
+    ("rec709", Transfer::Rec709),
+    ("gamma2.2", Transfer::Gamma22),
+    ("gamma2.4", Transfer::Gamma24),
+    ("cineon", Transfer::Cineon),
+];
+
+impl Transfer {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        TRANSFERS
+            .iter()
+            .find(|(transfer_name, _)| *transfer_name == name)
+            .map(|(_, transfer)| *transfer)
+            .ok_or_else(|| format!("Unrecognized transfer function: {}", name))
+    }
+
+    // Code value to scene-linear
+    pub fn decode(self, value: f32) -> f32 {
+        match self {
+            Transfer::Linear => value,
+            Transfer::Srgb => {
+                if value <= 0.04045 {
+                    value / 12.92
+                } else {
+                    ((value + 0.055) / 1.055).powf(2.4)
+                }
+            }
+            Transfer::Rec709 => {
+                if value < 4.5 * REC709_BETA {
+                    value / 4.5
+                } else {
+                    ((value + REC709_ALPHA - 1.0) / REC709_ALPHA).powf(0.45f32.recip())
+                }
+            }
+            Transfer::Gamma22 => signed_pow(value, 2.2),
+            Transfer::Gamma24 => signed_pow(value, 2.4),
+            Transfer::Cineon => {
+                let offset = cineon_offset();
+                let exponent = (value * 1023.0 - CINEON_WHITE) * CINEON_DENSITY / CINEON_GAMMA;
+                (10.0f32.powf(exponent) - offset) / (1.0 - offset)
+            }
+        }
+    }
+
+    // Scene-linear to code value
+    pub fn encode(self, value: f32) -> f32 {
+        match self {
+            Transfer::Linear => value,
+            Transfer::Srgb => {
+                if value <= 0.003_130_8 {
+                    value * 12.92
+                } else {
+                    1.055 * value.powf(2.4f32.recip()) - 0.055
+                }
+            }
+            Transfer::Rec709 => {
+                if value < REC709_BETA {
+                    value * 4.5
+                } else {
+                    REC709_ALPHA * value.powf(0.45) - (REC709_ALPHA - 1.0)
+                }
+            }
+            Transfer::Gamma22 => signed_pow(value, 2.2f32.recip()),
+            Transfer::Gamma24 => signed_pow(value, 2.4f32.recip()),
+            Transfer::Cineon => {
+                let offset = cineon_offset();
+                // Log of anything at or below the offset is undefined
+                let density = (value * (1.0 - offset) + offset).max(f32::MIN_POSITIVE);
+                (CINEON_WHITE + density.log10() * CINEON_GAMMA / CINEON_DENSITY) / 1023.0
+            }
+        }
+    }
+}
+
+fn cineon_offset() -> f32 {
+    10.0f32.powf((CINEON_BLACK - CINEON_WHITE) * CINEON_DENSITY / CINEON_GAMMA)
+}
+
+// Mirrors the curve for negative values
+// so that they survive a round trip
+fn signed_pow(value: f32, exponent: f32) -> f32 {
+    value.signum() * value.abs().powf(exponent)
+}
@@ -0,0 +1,226 @@
+// Color configurations describe named colorspaces by the
+// transforms that take them to and from the reference space.
+//
+//     # Comments run to the end of the line
+//     role scene_linear = acescg
+//
+//     colorspace acescg
+//         matrix 1.705 -0.622 -0.083 -0.130 1.141 -0.011 -0.024 -0.129 1.153
+//
+//     colorspace log_film
+//         transfer cineon
+//         lut "luts/film_to_linear.cube"
+//         from_reference
+//         lut "luts/linear_to_film.cube" tetrahedral
+//
+// Transforms listed under a colorspace take it to the reference
+// space in order. Those following from_reference take the reference
+// space back to the colorspace. Otherwise, the inverse of the first
+// chain is used. LUT paths are relative to the configuration file
+// and may be followed by the interpolation to use for 3D tables.
+
+use super::{
+    lut::{self, Interpolation},
+    transform::{Processor, Transform},
+    Transfer, TRANSFERS,
+};
+use crate::utils::hash::StableHasher;
+use std::{
+    collections::HashMap,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+pub const SCENE_LINEAR: &str = "scene_linear";
+
+#[derive(Debug, Clone, Default)]
+pub struct Colorspace {
+    to_reference: Vec<Transform>,
+    from_reference: Option<Vec<Transform>>,
+}
+
+impl Colorspace {
+    pub fn to_reference(&self) -> Vec<Transform> {
+        self.to_reference.clone()
+    }
+
+    pub fn from_reference(&self) -> Result<Vec<Transform>, String> {
+        match &self.from_reference {
+            Some(transforms) => Ok(transforms.clone()),
+            None => self
+                .to_reference
+                .iter()
+                .rev()
+                .map(|transform| transform.inverse())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    roles: HashMap<String, String>,
+    colorspaces: HashMap<String, Colorspace>,
+    // Differs between configurations that may convert differently
+    fingerprint: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut roles = HashMap::new();
+        roles.insert(SCENE_LINEAR.into(), "linear".into());
+        let colorspaces = TRANSFERS
+            .iter()
+            .map(|(name, transfer)| {
+                let to_reference = match transfer {
+                    Transfer::Linear => vec![],
+                    _ => vec![Transform::Decode(*transfer)],
+                };
+                let colorspace = Colorspace {
+                    to_reference,
+                    from_reference: None,
+                };
+                (name.to_string(), colorspace)
+            })
+            .collect();
+        Self {
+            roles,
+            colorspaces,
+            fingerprint: 0,
+        }
+    }
+}
+
+impl Config {
+    // Builtin colorspaces remain available unless overridden
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let src = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&src, dir).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn parse(src: &str, dir: &Path) -> Result<Self, String> {
+        let mut config = Self::default();
+        let mut current: Option<(String, Colorspace)> = None;
+        // The source along with the modification time of each LUT
+        let mut hasher = StableHasher::new();
+        src.hash(&mut hasher);
+        let mut inverse = false;
+
+        for (number, line) in src.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let words = line
+                .split_whitespace()
+                .map(|word| word.trim_matches('"'))
+                .collect::<Vec<_>>();
+            let err = |message: &str| format!("Line {}: {}", number + 1, message);
+
+            let transform = match words.as_slice() {
+                [] => continue,
+                ["role", role, "=", colorspace] => {
+                    config
+                        .roles
+                        .insert(role.to_string(), colorspace.to_string());
+                    continue;
+                }
+                ["colorspace", name] => {
+                    if let Some((name, colorspace)) = current.take() {
+                        config.colorspaces.insert(name, colorspace);
+                    }
+                    current = Some((name.to_string(), Colorspace::default()));
+                    inverse = false;
+                    continue;
+                }
+                ["from_reference"] => {
+                    match current.as_mut() {
+                        Some((_, colorspace)) => colorspace.from_reference = Some(vec![]),
+                        None => return Err(err("from_reference outside of a colorspace")),
+                    }
+                    inverse = true;
+                    continue;
+                }
+                ["transfer", name] => {
+                    let transfer = Transfer::parse(name).map_err(|e| err(&e))?;
+                    if inverse {
+                        Transform::Encode(transfer)
+                    } else {
+                        Transform::Decode(transfer)
+                    }
+                }
+                ["matrix", values @ ..] => {
+                    let values = values
+                        .iter()
+                        .map(|value| value.parse::<f32>().ok())
+                        .collect::<Option<Vec<_>>>()
+                        .filter(|values| values.len() == 9)
+                        .ok_or_else(|| err("Matrix takes nine numbers"))?;
+                    let mut matrix = [0.0; 9];
+                    matrix.copy_from_slice(&values);
+                    Transform::Matrix(matrix)
+                }
+                ["lut", path, interpolation @ ..] => {
+                    let interpolation = match interpolation {
+                        [] => Interpolation::Trilinear,
+                        [name] => Interpolation::parse(name).map_err(|e| err(&e))?,
+                        _ => return Err(err("Unrecognized statement")),
+                    };
+                    let path = relative_to(dir, path);
+                    let lut = lut::load(&path).map_err(|e| err(&e))?;
+                    let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+                    (&path, modified).hash(&mut hasher);
+                    Transform::Lut(Arc::new(lut), interpolation)
+                }
+                _ => return Err(err("Unrecognized statement")),
+            };
+
+            match current.as_mut() {
+                Some((_, colorspace)) => match colorspace.from_reference.as_mut() {
+                    Some(transforms) if inverse => transforms.push(transform),
+                    _ => colorspace.to_reference.push(transform),
+                },
+                None => return Err(err("Transform outside of a colorspace")),
+            }
+        }
+
+        if let Some((name, colorspace)) = current.take() {
+            config.colorspaces.insert(name, colorspace);
+        }
+        config.fingerprint = hasher.finish();
+        Ok(config)
+    }
+
+    pub fn fingerprint(&self) -> u64 {
+        self.fingerprint
+    }
+
+    // Roles resolve to the colorspace they refer to
+    pub fn colorspace(&self, name: &str) -> Result<&Colorspace, String> {
+        self.colorspaces
+            .get(self.resolve(name))
+            .ok_or_else(|| format!("Unrecognized colorspace: {}", name))
+    }
+
+    pub fn processor(&self, from: &str, to: &str) -> Result<Processor, String> {
+        if self.resolve(from) == self.resolve(to) {
+            return Ok(Processor::default());
+        }
+        let mut transforms = self.colorspace(from)?.to_reference();
+        transforms.extend(self.colorspace(to)?.from_reference()?);
+        Ok(Processor::new(transforms))
+    }
+
+    fn resolve<'a>(&'a self, name: &'a str) -> &'a str {
+        self.roles.get(name).map(String::as_str).unwrap_or(name)
+    }
+}
+
+fn relative_to(dir: &Path, path: &str) -> PathBuf {
+    let path = Path::new(path);
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        dir.join(path)
+    }
+}
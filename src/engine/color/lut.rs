@@ -0,0 +1,389 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+pub type Rgb = [f32; 3];
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Interpolation {
+    Trilinear,
+    Tetrahedral,
+}
+
+impl Interpolation {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "trilinear" => Ok(Interpolation::Trilinear),
+            "tetrahedral" => Ok(Interpolation::Tetrahedral),
+            _ => Err(format!("Unrecognized interpolation: {}", name)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Lut1d {
+    min: Rgb,
+    max: Rgb,
+    table: Vec<Rgb>,
+}
+
+impl Lut1d {
+    pub fn apply(&self, rgb: Rgb) -> Rgb {
+        let last = self.table.len() - 1;
+        let mut out = [0.0; 3];
+        for (c, out) in out.iter_mut().enumerate() {
+            let t = unit(rgb[c], self.min[c], self.max[c]) * last as f32;
+            let lo = t.floor() as usize;
+            let hi = (lo + 1).min(last);
+            *out = lerp(self.table[lo][c], self.table[hi][c], t - lo as f32);
+        }
+        out
+    }
+
+    // Only meaningful for tables that increase monotonically
+    pub fn apply_inverse(&self, rgb: Rgb) -> Rgb {
+        let last = self.table.len() - 1;
+        let mut out = [0.0; 3];
+        for (c, out) in out.iter_mut().enumerate() {
+            let i = self.table.partition_point(|entry| entry[c] < rgb[c]);
+            let t = if i == 0 {
+                0.0
+            } else if i > last {
+                last as f32
+            } else {
+                let lo = self.table[i - 1][c];
+                let hi = self.table[i][c];
+                let frac = if hi > lo {
+                    (rgb[c] - lo) / (hi - lo)
+                } else {
+                    0.0
+                };
+                (i - 1) as f32 + frac
+            };
+            *out = lerp(self.min[c], self.max[c], t / last as f32);
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Lut3d {
+    min: Rgb,
+    max: Rgb,
+    size: usize,
+    // Red varies fastest, as in the .cube format
+    table: Vec<Rgb>,
+}
+
+impl Lut3d {
+    pub fn trilinear(&self, rgb: Rgb) -> Rgb {
+        let (lo, hi, frac) = self.lattice(rgb);
+        let mut out = [0.0; 3];
+        for (c, out) in out.iter_mut().enumerate() {
+            let c00 = lerp(
+                self.at(lo[0], lo[1], lo[2])[c],
+                self.at(hi[0], lo[1], lo[2])[c],
+                frac[0],
+            );
+            let c10 = lerp(
+                self.at(lo[0], hi[1], lo[2])[c],
+                self.at(hi[0], hi[1], lo[2])[c],
+                frac[0],
+            );
+            let c01 = lerp(
+                self.at(lo[0], lo[1], hi[2])[c],
+                self.at(hi[0], lo[1], hi[2])[c],
+                frac[0],
+            );
+            let c11 = lerp(
+                self.at(lo[0], hi[1], hi[2])[c],
+                self.at(hi[0], hi[1], hi[2])[c],
+                frac[0],
+            );
+            *out = lerp(lerp(c00, c10, frac[1]), lerp(c01, c11, frac[1]), frac[2]);
+        }
+        out
+    }
+
+    pub fn tetrahedral(&self, rgb: Rgb) -> Rgb {
+        let (lo, hi, f) = self.lattice(rgb);
+        let corner = |r: bool, g: bool, b: bool| {
+            self.at(
+                if r { hi[0] } else { lo[0] },
+                if g { hi[1] } else { lo[1] },
+                if b { hi[2] } else { lo[2] },
+            )
+        };
+        // Pick the tetrahedron containing the color by ordering
+        // its fractional components, then weight its vertices
+        let (weights, vertices) = if f[0] > f[1] {
+            if f[1] > f[2] {
+                (
+                    [1.0 - f[0], f[0] - f[1], f[1] - f[2], f[2]],
+                    [corner(true, false, false), corner(true, true, false)],
+                )
+            } else if f[0] > f[2] {
+                (
+                    [1.0 - f[0], f[0] - f[2], f[2] - f[1], f[1]],
+                    [corner(true, false, false), corner(true, false, true)],
+                )
+            } else {
+                (
+                    [1.0 - f[2], f[2] - f[0], f[0] - f[1], f[1]],
+                    [corner(false, false, true), corner(true, false, true)],
+                )
+            }
+        } else if f[2] > f[1] {
+            (
+                [1.0 - f[2], f[2] - f[1], f[1] - f[0], f[0]],
+                [corner(false, false, true), corner(false, true, true)],
+            )
+        } else if f[2] > f[0] {
+            (
+                [1.0 - f[1], f[1] - f[2], f[2] - f[0], f[0]],
+                [corner(false, true, false), corner(false, true, true)],
+            )
+        } else {
+            (
+                [1.0 - f[1], f[1] - f[0], f[0] - f[2], f[2]],
+                [corner(false, true, false), corner(true, true, false)],
+            )
+        };
+        let first = corner(false, false, false);
+        let last = corner(true, true, true);
+        let mut out = [0.0; 3];
+        for (c, out) in out.iter_mut().enumerate() {
+            *out = weights[0] * first[c]
+                + weights[1] * vertices[0][c]
+                + weights[2] * vertices[1][c]
+                + weights[3] * last[c];
+        }
+        out
+    }
+
+    // Lower and upper lattice coordinates
+    // surrounding a color and the position between them
+    fn lattice(&self, rgb: Rgb) -> ([usize; 3], [usize; 3], Rgb) {
+        let last = self.size - 1;
+        let mut lo = [0; 3];
+        let mut hi = [0; 3];
+        let mut frac = [0.0; 3];
+        for c in 0..3 {
+            let t = unit(rgb[c], self.min[c], self.max[c]) * last as f32;
+            lo[c] = t.floor() as usize;
+            hi[c] = (lo[c] + 1).min(last);
+            frac[c] = t - lo[c] as f32;
+        }
+        (lo, hi, frac)
+    }
+
+    fn at(&self, r: usize, g: usize, b: usize) -> Rgb {
+        self.table[(b * self.size + g) * self.size + r]
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Lut {
+    OneD(Lut1d),
+    ThreeD(Lut3d),
+}
+
+impl Lut {
+    // Interpolation only matters for 3D LUTs
+    pub fn apply(&self, rgb: Rgb, interpolation: Interpolation) -> Rgb {
+        match self {
+            Lut::OneD(lut) => lut.apply(rgb),
+            Lut::ThreeD(lut) => match interpolation {
+                Interpolation::Trilinear => lut.trilinear(rgb),
+                Interpolation::Tetrahedral => lut.tetrahedral(rgb),
+            },
+        }
+    }
+}
+
+type CacheEntry = (Option<SystemTime>, Arc<Lut>);
+
+// Parsed LUTs by filename. Files are read again
+// only when their modification time changes.
+#[derive(Debug, Default)]
+pub struct Cache {
+    entries: Mutex<HashMap<PathBuf, CacheEntry>>,
+}
+
+impl Cache {
+    pub fn load(&self, path: &Path) -> Result<Arc<Lut>, String> {
+        let modified = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let mut entries = self.entries.lock().map_err(|e| format!("{}", e))?;
+        if let Some((cached, lut)) = entries.get(path) {
+            if *cached == modified {
+                return Ok(lut.clone());
+            }
+        }
+        let lut = Arc::new(load(path)?);
+        entries.insert(path.to_path_buf(), (modified, lut.clone()));
+        Ok(lut)
+    }
+}
+
+pub fn load(path: &Path) -> Result<Lut, String> {
+    let src = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse_cube(&src).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn parse_cube(src: &str) -> Result<Lut, String> {
+    let mut size_1d = None;
+    let mut size_3d = None;
+    let mut min = [0.0; 3];
+    let mut max = [1.0; 3];
+    let mut table = Vec::new();
+
+    for (number, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let keyword = words.next().unwrap_or_default();
+        let err = |message: &str| format!("Line {}: {}", number + 1, message);
+        match keyword {
+            "TITLE" => {}
+            "LUT_1D_SIZE" => size_1d = Some(size(words.next()).ok_or_else(|| err("Invalid size"))?),
+            "LUT_3D_SIZE" => size_3d = Some(size(words.next()).ok_or_else(|| err("Invalid size"))?),
+            "DOMAIN_MIN" => min = rgb(words).ok_or_else(|| err("Invalid domain"))?,
+            "DOMAIN_MAX" => max = rgb(words).ok_or_else(|| err("Invalid domain"))?,
+            "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                let range = words
+                    .map(|word| word.parse::<f32>().ok())
+                    .collect::<Option<Vec<_>>>()
+                    .filter(|range| range.len() == 2)
+                    .ok_or_else(|| err("Invalid input range"))?;
+                min = [range[0]; 3];
+                max = [range[1]; 3];
+            }
+            _ => table.push(rgb(line.split_whitespace()).ok_or_else(|| err("Invalid entry"))?),
+        }
+    }
+
+    match (size_1d, size_3d) {
+        (Some(size), None) if table.len() == size => Ok(Lut::OneD(Lut1d { min, max, table })),
+        (None, Some(size)) if table.len() == size * size * size => Ok(Lut::ThreeD(Lut3d {
+            min,
+            max,
+            size,
+            table,
+        })),
+        (None, None) => Err("Missing LUT size".to_string()),
+        (Some(_), Some(_)) => Err("LUT cannot be both 1D and 3D".to_string()),
+        _ => Err("LUT entry count does not match its size".to_string()),
+    }
+}
+
+fn size(word: Option<&str>) -> Option<usize> {
+    word.and_then(|word| word.parse::<usize>().ok())
+        .filter(|size| *size > 1)
+}
+
+fn rgb<'a, I>(words: I) -> Option<Rgb>
+where
+    I: Iterator<Item = &'a str>,
+{
+    let values = words
+        .map(|word| word.parse::<f32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    match values.as_slice() {
+        [r, g, b] => Some([*r, *g, *b]),
+        _ => None,
+    }
+}
+
+// Position of a value within a domain, clamped to 0..1
+fn unit(value: f32, min: f32, max: f32) -> f32 {
+    ((value - min) / (max - min)).clamp(0.0, 1.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Affine, so any interpolation between entries should be exact
+    fn affine(rgb: Rgb) -> Rgb {
+        let [r, g, b] = rgb;
+        [0.5 * r + 0.2 * g + 0.1, 0.8 * g + 0.1 * b, 1.0 - b]
+    }
+
+    // Red changes fastest in a cube file
+    fn cube(size: usize) -> String {
+        let mut src = format!("TITLE \"affine\"\nLUT_3D_SIZE {}\n", size);
+        let step = 1.0 / (size - 1) as f32;
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let [r, g, b] = affine([r as f32 * step, g as f32 * step, b as f32 * step]);
+                    src.push_str(&format!("{} {} {}\n", r, g, b));
+                }
+            }
+        }
+        src
+    }
+
+    fn assert_close(a: Rgb, b: Rgb) {
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a - b).abs() < 1e-5, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn interpolates_3d() {
+        let lut = parse_cube(&cube(3)).unwrap();
+        let samples = [
+            [0.0, 0.0, 0.0],
+            [1.0, 1.0, 1.0],
+            [0.3, 0.7, 0.1],
+            [0.9, 0.2, 0.55],
+        ];
+        for rgb in samples.iter() {
+            for interpolation in &[Interpolation::Trilinear, Interpolation::Tetrahedral] {
+                assert_close(lut.apply(*rgb, *interpolation), affine(*rgb));
+            }
+        }
+        // Past the domain holds at the edges
+        assert_close(
+            lut.apply([1.5, -0.5, 0.5], Interpolation::Tetrahedral),
+            affine([1.0, 0.0, 0.5]),
+        );
+    }
+
+    #[test]
+    fn interpolates_1d_and_inverts() {
+        let lut = match parse_cube("LUT_1D_SIZE 3\n0 0 0\n0.25 0.25 0.25\n1 1 1\n").unwrap() {
+            Lut::OneD(lut) => lut,
+            _ => panic!("Expected a 1D LUT"),
+        };
+        assert_close(lut.apply([0.5, 0.25, 0.75]), [0.25, 0.125, 0.625]);
+        for rgb in &[[0.5, 0.25, 0.75], [0.0, 1.0, 0.1]] {
+            assert_close(lut.apply_inverse(lut.apply(*rgb)), *rgb);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_cubes() {
+        for src in &[
+            "0 0 0\n1 1 1\n",
+            "LUT_1D_SIZE 3\n0 0 0\n1 1 1\n",
+            "LUT_3D_SIZE 2\nLUT_1D_SIZE 2\n0 0 0\n1 1 1\n",
+            "LUT_1D_SIZE 2\n0 0\n1 1 1\n",
+        ] {
+            assert!(parse_cube(src).is_err(), "{}", src);
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use super::{
+    lut::{Interpolation, Lut, Rgb},
+    Transfer,
+};
+use crate::{image::Image, utils::Rect};
+use rayon::prelude::*;
+use std::sync::Arc;
+
+pub type Matrix = [f32; 9];
+
+#[derive(Debug, Clone)]
+pub enum Transform {
+    Decode(Transfer),
+    Encode(Transfer),
+    Matrix(Matrix),
+    Lut(Arc<Lut>, Interpolation),
+    InverseLut(Arc<Lut>),
+}
+
+impl Transform {
+    pub fn inverse(&self) -> Result<Self, String> {
+        match self {
+            Transform::Decode(transfer) => Ok(Transform::Encode(*transfer)),
+            Transform::Encode(transfer) => Ok(Transform::Decode(*transfer)),
+            Transform::Matrix(matrix) => invert(matrix)
+                .map(Transform::Matrix)
+                .ok_or_else(|| "Matrix cannot be inverted".to_string()),
+            Transform::Lut(lut, _) => match lut.as_ref() {
+                Lut::OneD(_) => Ok(Transform::InverseLut(lut.clone())),
+                Lut::ThreeD(_) => Err("3D LUTs cannot be inverted".to_string()),
+            },
+            Transform::InverseLut(lut) => Ok(Transform::Lut(lut.clone(), Interpolation::Trilinear)),
+        }
+    }
+
+    pub fn apply(&self, rgb: Rgb) -> Rgb {
+        match self {
+            Transform::Decode(transfer) => rgb.map(|c| transfer.decode(c)),
+            Transform::Encode(transfer) => rgb.map(|c| transfer.encode(c)),
+            Transform::Matrix(m) => [
+                m[0] * rgb[0] + m[1] * rgb[1] + m[2] * rgb[2],
+                m[3] * rgb[0] + m[4] * rgb[1] + m[5] * rgb[2],
+                m[6] * rgb[0] + m[7] * rgb[1] + m[8] * rgb[2],
+            ],
+            Transform::Lut(lut, interpolation) => lut.apply(rgb, *interpolation),
+            Transform::InverseLut(lut) => match lut.as_ref() {
+                Lut::OneD(lut) => lut.apply_inverse(rgb),
+                // Never constructed by inverse()
+                Lut::ThreeD(_) => rgb,
+            },
+        }
+    }
+}
+
+// A chain of transforms converting between two colorspaces
+#[derive(Debug, Clone, Default)]
+pub struct Processor {
+    transforms: Vec<Transform>,
+}
+
+impl Processor {
+    pub fn new(transforms: Vec<Transform>) -> Self {
+        Self { transforms }
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.transforms.is_empty()
+    }
+
+    pub fn apply_rgb(&self, rgb: Rgb) -> Rgb {
+        self.transforms
+            .iter()
+            .fold(rgb, |rgb, transform| transform.apply(rgb))
+    }
+
+    pub fn apply(&self, image: &mut Image) {
+        self.apply_region(image, None)
+    }
+
+    // Leaves pixels outside the region of the frame untouched
+    pub fn apply_region(&self, image: &mut Image, region: Option<Rect>) {
+        if self.is_identity() {
+            return;
+        }
+        let region = image.local_region(region);
+        let [r, g, b] = match image.rgb_mut() {
+            Some(rgb) => rgb,
+            None => {
+                // Treat gray as equal parts of each primary
+                let indices = image.color_indices();
+                image
+                    .par_channels_mut()
+                    .enumerate()
+                    .filter(|(i, _)| indices.contains(i))
+                    .for_each(|(_, channel)| {
+                        channel.par_region_lines_mut(region).for_each(|line| {
+                            for element in line.iter_mut() {
+                                *element = self.apply_rgb([*element; 3])[0];
+                            }
+                        })
+                    });
+                return;
+            }
+        };
+        r.par_region_lines_mut(region)
+            .zip(g.par_region_lines_mut(region))
+            .zip(b.par_region_lines_mut(region))
+            .for_each(|((r, g), b)| {
+                for ((r, g), b) in r.iter_mut().zip(g.iter_mut()).zip(b.iter_mut()) {
+                    let rgb = self.apply_rgb([*r, *g, *b]);
+                    *r = rgb[0];
+                    *g = rgb[1];
+                    *b = rgb[2];
+                }
+            });
+    }
+}
+
+fn invert(m: &Matrix) -> Option<Matrix> {
+    let cofactors = [
+        m[4] * m[8] - m[5] * m[7],
+        m[2] * m[7] - m[1] * m[8],
+        m[1] * m[5] - m[2] * m[4],
+        m[5] * m[6] - m[3] * m[8],
+        m[0] * m[8] - m[2] * m[6],
+        m[2] * m[3] - m[0] * m[5],
+        m[3] * m[7] - m[4] * m[6],
+        m[1] * m[6] - m[0] * m[7],
+        m[0] * m[4] - m[1] * m[3],
+    ];
+    let determinant = m[0] * cofactors[0] + m[1] * cofactors[3] + m[2] * cofactors[6];
+    if determinant.abs() < f32::EPSILON {
+        None
+    } else {
+        Some(cofactors.map(|c| c / determinant))
+    }
+}
@@ -4,10 +4,24 @@ use crate::utils::Value;
 pub struct Desc {
     pub name: &'static str,
     pub kind: Value,
+    // Whether the value names a file read while rendering
+    pub file: bool,
 }
 
 impl Desc {
     pub fn new(name: &'static str, kind: Value) -> Self {
-        Self { name, kind }
+        Self {
+            name,
+            kind,
+            file: false,
+        }
+    }
+
+    // A text control naming a file
+    pub fn file(name: &'static str) -> Self {
+        Self {
+            file: true,
+            ..Self::new(name, Value::Text("".into()))
+        }
     }
 }
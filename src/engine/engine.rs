@@ -1,15 +1,26 @@
 use crate::{
-    graph::{Graph, Node},
+    cache::{Cache, Key, KEY_VERSION},
+    color,
+    format::{Format, Formats},
+    graph::{Graph, Input, Node},
     image::Image,
+    mdl::History,
     plugin::{self, Plugin},
-    utils::{Id, Value},
+    utils::{hash::StableHasher, Id, Rect, Value, Vec2I},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    hash::{Hash, Hasher},
+    mem,
 };
-use std::collections::HashMap;
 
 #[derive(Default)]
 pub struct Engine {
-    next_id: Id,
-    pub viewing: Id,
+    pub viewing: Option<Id>,
+    pub frame: isize,
+    pub format: Format,
+    pub formats: Formats,
 
     pub plugins: HashMap<Id, Plugin>,
     pub plugin_names: HashMap<String, Id>,
@@ -19,7 +30,10 @@ pub struct Engine {
 
     pub graph: Graph,
     pub controls: HashMap<Id, Vec<Value>>,
-    pub images: HashMap<Id, Image>,
+    pub cache: Cache,
+    pub colors: color::Config,
+    pub luts: color::lut::Cache,
+    pub history: History,
 }
 
 impl Engine {
@@ -30,58 +44,556 @@ impl Engine {
     }
 
     pub fn insert_node(&mut self, node: Node, name: String) -> Id {
-        let id = self.next_id;
-        self.next_id += 1;
-        self.images.insert(id, Default::default());
+        // Ids are graph indices, so a node made again
+        // after a delete may take the same id
+        let id = self.graph.insert_node();
         self.nodes.insert(id, node);
         if let Some(plugin) = self.plugins.get(&node.plugin) {
             let desc = plugin.desc();
-            self.graph.insert_node();
             self.controls.insert(id, desc.controls());
             self.node_names.insert(name, id);
         }
         id
     }
 
-    pub fn delete_node(&mut self, id: Id) {
-        self.images.remove(&id);
+    pub fn node_name(&self, id: Id) -> Option<&str> {
+        self.node_names
+            .iter()
+            .find(|(_, v)| **v == id)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn rename_node(&mut self, id: Id, name: String) -> Result<(), String> {
+        if self.node_names.contains_key(&name) {
+            return Err(format!("Node name already taken: {}", name));
+        }
+        let old = self.node_name(id).ok_or("Node not found")?.to_string();
+        self.node_names.remove(&old);
+        self.node_names.insert(name, id);
+        Ok(())
+    }
+
+    // Copies nodes and their controls, giving the copies the names
+    // in order. Wiring between the nodes is copied to match. With
+    // wired, so are inputs from nodes outside of those copied.
+    pub fn copy_nodes(
+        &mut self,
+        ids: &[Id],
+        names: &[String],
+        wired: bool,
+    ) -> Result<Vec<Id>, String> {
+        if ids.len() != names.len() {
+            return Err("Each node copied needs a name".to_string());
+        }
+        let mut taken = HashSet::new();
+        for name in names {
+            if self.node_names.contains_key(name) || !taken.insert(name) {
+                return Err(format!("Node name already taken: {}", name));
+            }
+        }
+        let mut sources = Vec::with_capacity(ids.len());
+        for id in ids {
+            let node = self.nodes.get(id).ok_or("Node not found")?;
+            let controls = self.controls.get(id).ok_or("Controls not found")?;
+            sources.push((*id, node.plugin, controls.clone()));
+        }
+
+        let mut copies = HashMap::new();
+        for ((id, plugin, controls), name) in sources.into_iter().zip(names) {
+            let copy = self.insert_node(Node::new(plugin), name.clone());
+            self.controls.insert(copy, controls);
+            copies.insert(id, copy);
+        }
+        for (id, copy) in copies.iter() {
+            let node = self.nodes.get(id).ok_or("Node not found")?;
+            let plugin = self.plugins.get(&node.plugin).ok_or("Plugin not found")?;
+            for input in 0..plugin.desc().inputs_len() as Input {
+                let upstream = match self.graph.input(*id, input) {
+                    Some(upstream) => upstream,
+                    None => continue,
+                };
+                match copies.get(&upstream) {
+                    Some(upstream) => self.graph.connect(*copy, *upstream, input),
+                    None if wired => self.graph.connect(*copy, upstream, input),
+                    None => (),
+                }
+            }
+        }
+        Ok(ids.iter().map(|id| copies[id]).collect())
+    }
+
+    // Removes the node along with its wiring, returning the inputs
+    // of other nodes it was connected to. If the node was being
+    // viewed, its first connected input is viewed instead.
+    pub fn delete_node(&mut self, id: Id) -> Result<Vec<(Id, Input)>, String> {
+        let node = self.nodes.get(&id).ok_or("Node not found")?;
+        if self.viewing == Some(id) {
+            let inputs = self
+                .plugins
+                .get(&node.plugin)
+                .map_or(0, |plugin| plugin.desc().inputs_len());
+            self.viewing = (0..inputs).find_map(|input| self.graph.input(id, input as u8));
+        }
+        let disconnected = self.graph.consumers(id);
+
         self.nodes.remove(&id);
         self.graph.delete_node(id);
         self.controls.remove(&id);
+        if let Some(name) = self.node_name(id).map(String::from) {
+            self.node_names.remove(&name);
+        }
+        Ok(disconnected)
+    }
 
-        let to_remove = self
-            .node_names
-            .iter()
-            .filter_map(|(k, v)| if *v == id { Some(k.clone()) } else { None })
-            .next()
-            .unwrap();
-        self.node_names.remove(&to_remove);
+    // Outputs are keyed by the plugin, its controls and the keys of
+    // its inputs, plus the frame for animated plugins, the color
+    // configuration for those converting colors and the project
+    // format for those following it, so returning to an earlier
+    // state finds its results again. Files named by controls add
+    // their modification time so that edits to them are picked up.
+    // Keys name files on disk, so they also take the version of the
+    // crate and of the keys.
+    fn keys(&self, id: Id) -> Result<HashMap<Id, Key>, String> {
+        let mut keys = HashMap::new();
+        for id in self.graph.render_queue(id).iter().rev() {
+            let node = self.nodes.get(id).ok_or("Node not found")?;
+            let plugin = self.plugins.get(&node.plugin).ok_or("Plugin not found")?;
+            let controls = self.controls.get(id).ok_or("Controls not found")?;
+            let mut hasher = StableHasher::new();
+            env!("CARGO_PKG_VERSION").hash(&mut hasher);
+            KEY_VERSION.hash(&mut hasher);
+            plugin.desc().name().hash(&mut hasher);
+            if plugin.is_animated() {
+                self.frame.hash(&mut hasher);
+            }
+            if plugin.is_color_managed() {
+                self.colors.fingerprint().hash(&mut hasher);
+            }
+            if plugin.is_format_dependent() {
+                self.format.hash(&mut hasher);
+            }
+            for (i, control) in controls.iter().enumerate() {
+                control.hash(&mut hasher);
+                if let (true, Value::Text(text)) = (plugin.desc().is_file(i), control) {
+                    let modified = fs::metadata(text).and_then(|m| m.modified()).ok();
+                    modified.hash(&mut hasher);
+                }
+            }
+            for input in 0..plugin.desc().inputs_len() {
+                let upstream = self.graph.input(*id, input as u8);
+                upstream.map(|upstream| keys[&upstream]).hash(&mut hasher);
+            }
+            keys.insert(*id, hasher.finish());
+        }
+        Ok(keys)
+    }
+
+    // The cached output of a node, if any
+    pub fn image(&self, id: Id) -> Option<&Image> {
+        let keys = self.keys(id).ok()?;
+        self.cache.get(keys[&id])
     }
 
     pub fn render(&mut self) -> Result<&Image, String> {
-        let queue = self.graph.render_queue(self.viewing);
-        for id in queue.iter().rev() {
-            let node = self.nodes.get(id).ok_or("Node not found")?;
-            if !node.dirty {
+        self.render_region(None)
+    }
+
+    // Only computes what is needed for a region of the viewed
+    // node, or all of it for None. Pixels outside the region
+    // are undefined. Nodes already holding what is asked of
+    // them in the cache are reused.
+    pub fn render_region(&mut self, region: Option<Rect>) -> Result<&Image, String> {
+        let viewed = self.viewing.ok_or("No node is being viewed")?;
+        let keys = self.keys(viewed)?;
+        let viewing = keys[&viewed];
+
+        // Nodes that would give the same output share a key, and only
+        // the last of them, which follows all of their consumers, renders
+        let mut seen = HashSet::new();
+        let mut queue = self
+            .graph
+            .render_queue(viewed)
+            .into_iter()
+            .rev()
+            .filter(|id| seen.insert(keys[id]))
+            .collect::<Vec<_>>();
+        queue.reverse();
+
+        // Requests flow upstream from nodes that have to render,
+        // with each asking its inputs for enough to cover every
+        // region asked of it
+        let mut regions = HashMap::new();
+        regions.insert(viewing, region);
+        let mut pending = Vec::new();
+        for id in queue.iter() {
+            let key = keys[id];
+            let region = match regions.get(&key) {
+                Some(region) => *region,
+                None => continue,
+            };
+            if self.cache.lookup(key, region) {
                 continue;
             }
-            let plugin = self
-                .plugins
-                .get(&node.plugin)
-                .ok_or("Plugin not found")?;
+            let node = self.nodes.get(id).ok_or("Node not found")?;
+            let plugin = self.plugins.get(&node.plugin).ok_or("Plugin not found")?;
             let controls = self.controls.get(id).ok_or("Controls not found")?;
-            let inputs: Vec<_> = (0..plugin.desc().inputs_len())
-                .flat_map(|input| {
-                    self.graph
-                        .input(*id, input as u8)
-                        .map(|node| self.images.get(&node))
-                })
+            let inputs = (0..plugin.desc().inputs_len())
+                .map(|input| self.graph.input(*id, input as u8).map(|id| keys[&id]))
+                .collect::<Vec<_>>();
+            for (input, upstream) in inputs.iter().enumerate() {
+                if let Some(upstream) = upstream {
+                    let needed = plugin.roi(input, region, controls);
+                    regions
+                        .entry(*upstream)
+                        .and_modify(|existing: &mut Option<Rect>| {
+                            *existing = existing.zip(needed).map(|(a, b)| a.union(b))
+                        })
+                        .or_insert(needed);
+                }
+            }
+            pending.push((*id, key, inputs));
+        }
+
+        while let Some((id, key, inputs)) = pending.pop() {
+            let node = self.nodes.get(&id).ok_or("Node not found")?;
+            let plugin = self.plugins.get(&node.plugin).ok_or("Plugin not found")?;
+            let controls = self.controls.get(&id).ok_or("Controls not found")?;
+            for input in inputs.iter().flatten() {
+                self.cache.touch(*input);
+            }
+            let images: Vec<_> = inputs
+                .iter()
+                .map(|input| input.and_then(|input| self.cache.get(input)))
                 .collect();
-            let render = plugin.render(inputs.as_slice(), controls.as_slice())?;
-            self.images.entry(*id).and_modify(|e| *e = render);
+            let region = regions.get(&key).copied().flatten();
+            let ctx = plugin::Context {
+                colors: &self.colors,
+                luts: &self.luts,
+                region,
+                frame: self.frame,
+                format: self.format,
+                formats: &self.formats,
+            };
+            let mut render = plugin.render(images.as_slice(), controls.as_slice(), &ctx)?;
+            // Only keep what was asked for so that
+            // each node holds no more than it needs
+            if let Some(region) = region {
+                let data = render.data_window();
+                if data.intersect(region) != data {
+                    render = render.reframed(data.intersect(region));
+                }
+            }
+            self.cache.insert(key, render, region)?;
+
+            // Keep the viewed node and the inputs of nodes still to render
+            let waiting = pending
+                .iter()
+                .flat_map(|(_, _, inputs)| inputs.iter().flatten().copied())
+                .collect::<HashSet<_>>();
+            self.cache
+                .evict(|key| key == viewing || waiting.contains(&key));
         }
-        self.images
-            .get(&self.viewing)
+        self.cache
+            .get(viewing)
             .ok_or_else(|| "Comp image not found".into())
     }
+
+    // Nodes upstream of the viewed one that need all of their input
+    // whatever region is asked of them, like resize. Rendering in
+    // tiles can't keep what they take within a memory budget.
+    pub fn untiled(&self) -> Result<Vec<String>, String> {
+        let viewed = self.viewing.ok_or("No node is being viewed")?;
+        let mut names = Vec::new();
+        for id in self.graph.render_queue(viewed) {
+            let node = self.nodes.get(&id).ok_or("Node not found")?;
+            let plugin = self.plugins.get(&node.plugin).ok_or("Plugin not found")?;
+            let controls = self.controls.get(&id).ok_or("Controls not found")?;
+            let whole = (0..plugin.desc().inputs_len()).any(|input| {
+                self.graph.input(id, input as u8).is_some()
+                    && plugin.roi(input, Some(Rect::default()), controls).is_none()
+            });
+            if whole {
+                names.push(self.node_name(id).ok_or("Node name not found")?.into());
+            }
+        }
+        Ok(names)
+    }
+
+    // Renders the viewed node a tile at a time, handing tiles to
+    // the sink along with the frame from top to bottom. Tiles span
+    // the width of the frame so that sources are read once per row
+    // of tiles and finished rows can be written out right away.
+    // Their height keeps the pixels held by every node upstream
+    // within the budget in bytes, apart from the inputs of nodes
+    // listed by untiled. Those are taken in full, though only
+    // once while the cache holds on to them.
+    pub fn render_tiled<F>(&mut self, budget: usize, mut sink: F) -> Result<(), String>
+    where
+        F: FnMut(Rect, &Image) -> Result<(), String>,
+    {
+        // Rendering nothing reveals the frame and the channels each
+        // node gives. It goes through a cache of its own, where every
+        // node renders and nothing is evicted, so the counts don't
+        // depend on what the shared cache happens to hold. Only the
+        // inputs of untiled nodes take any memory there.
+        let shared = mem::take(&mut self.cache);
+        let frame = self
+            .render_region(Some(Rect::default()))
+            .map(|image| image.display_window());
+        let probe = mem::replace(&mut self.cache, shared);
+        let frame = frame?;
+        let viewed = self.viewing.ok_or("No node is being viewed")?;
+        let channels: usize = self
+            .keys(viewed)?
+            .values()
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .filter_map(|key| probe.get(*key))
+            .map(|image| image.channel_count())
+            .sum();
+        // Whatever was rendered in full is worth keeping
+        self.cache.merge(probe);
+        self.cache.evict(|_| false);
+        let row_bytes = channels * frame.size().x * mem::size_of::<f32>();
+        let rows = (budget / row_bytes.max(1)).max(1) as isize;
+
+        let mut top = frame.min.y;
+        while top < frame.max.y {
+            let bottom = frame.max.y.min(top + rows);
+            let tile = Rect::new(
+                Vec2I::new(frame.min.x, top),
+                Vec2I::new(frame.max.x, bottom),
+            );
+            sink(frame, self.render_region(Some(tile))?)?;
+            top = bottom;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mdl::{self, ParseError, Parser};
+
+    fn comp(src: &str) -> Engine {
+        let parser = Parser::default();
+        let mut engine = Engine::new();
+        for line in src.lines() {
+            match parser.parse(line) {
+                Ok(statement) => {
+                    mdl::apply(&mut engine, &statement).unwrap();
+                }
+                Err(ParseError::Empty) => (),
+                Err(e) => panic!("{}: {}", line, e),
+            }
+        }
+        engine
+    }
+
+    fn assert_same(a: &Image, b: &Image, region: Rect) {
+        let (a, b) = (a.reframed(region), b.reframed(region));
+        assert_eq!(a.channel_count(), b.channel_count());
+        for (a, b) in a.channels().zip(b.channels()) {
+            for (a, b) in a.elements().zip(b.elements()) {
+                assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+            }
+        }
+    }
+
+    // Each comp renders from scratch so nothing is shared
+    fn assert_region_matches(src: &str, region: Rect) {
+        let full = comp(src).render().unwrap().clone();
+        let part = comp(src).render_region(Some(region)).unwrap().clone();
+        assert_same(&full, &part, region);
+    }
+
+    fn rect(x: isize, y: isize, width: isize, height: isize) -> Rect {
+        Rect::new(Vec2I::new(x, y), Vec2I::new(x + width, y + height))
+    }
+
+    const NOISE: &str = "
+        new noise n
+        n.width = 64
+        n.height = 48
+        n.scale = 8.0
+    ";
+
+    #[test]
+    fn blur_region_matches_full() {
+        for size in &[0, 3, -2] {
+            let src = format!(
+                "{}
+                new blur b
+                b.bg <- n
+                b.size = {}
+                glob viewing = b",
+                NOISE, size
+            );
+            assert_region_matches(&src, rect(10, 7, 20, 18));
+            assert_region_matches(&src, rect(0, 0, 5, 48));
+        }
+    }
+
+    #[test]
+    fn sharpen_region_matches_full() {
+        for radius in &[0, 2, -3] {
+            let src = format!(
+                "{}
+                new sharpen s
+                s.bg <- n
+                s.radius = {}
+                s.amount = 1.5
+                glob viewing = s",
+                NOISE, radius
+            );
+            assert_region_matches(&src, rect(10, 7, 20, 18));
+        }
+    }
+
+    // Tiles follow on from one another down the frame
+    fn assert_tiled_matches(src: &str, budget: usize) {
+        let full = comp(src).render().unwrap().clone();
+        let mut engine = comp(src);
+        let mut next = full.display_window().min.y;
+        let mut tiles = 0;
+        engine
+            .render_tiled(budget, |frame, tile| {
+                assert_eq!(frame, full.display_window());
+                let rows = tile.data_window().intersect(frame);
+                assert_eq!(rows.min.y, next);
+                assert_same(&full, tile, rows);
+                next = rows.max.y;
+                tiles += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(next, full.display_window().max.y);
+        assert!(tiles > 1);
+    }
+
+    #[test]
+    fn tiled_matches_full() {
+        let src = format!(
+            "{}
+            new ramp r
+            r.width = 64
+            r.height = 48
+            new blur b
+            b.bg <- n
+            b.size = 2
+            new merge m
+            m.bg <- b
+            m.fg <- r
+            m.tx = 5
+            m.ty = -3
+            glob viewing = m",
+            NOISE
+        );
+        assert_tiled_matches(&src, 16 << 10);
+        assert!(comp(&src).untiled().unwrap().is_empty());
+    }
+
+    #[test]
+    fn tiled_reports_untiled_nodes() {
+        let src = format!(
+            "{}
+            new resize z
+            z.bg <- n
+            z.mode = \"scale\"
+            z.scale = 0.5
+            new blur b
+            b.bg <- z
+            b.size = 1
+            glob viewing = b",
+            NOISE
+        );
+        assert_tiled_matches(&src, 4 << 10);
+        assert_eq!(comp(&src).untiled().unwrap(), ["z"]);
+    }
+
+    #[test]
+    fn every_plugin_region_matches_full() {
+        let nodes = [
+            ("blur", "p.size = 3"),
+            ("sharpen", "p.radius = 2"),
+            ("defocus", "p.size = 2.5\np.blades = 6"),
+            ("convolve", "p.kernel <- k"),
+            (
+                "crop",
+                "p.left = -5\np.top = 3\np.right = 4\np.clip = false",
+            ),
+            ("edge_detect", "p.method = \"sobel\""),
+            ("edge_detect", "p.method = \"laplacian\""),
+            ("erode", "p.size = 1.5\np.channel = \"all\""),
+            ("dilate", "p.size = 2.0"),
+            ("matte_choke", "p.amount = 1.5\np.softness = 2.0"),
+            ("merge", "p.fg <- r\np.tx = 5\np.ty = -3"),
+            ("resize", "p.mode = \"fit\"\np.width = 40\np.height = 30"),
+            ("reformat", "p.format = \"40x30\"\np.mode = \"fill\""),
+            ("adjust", "p.saturation = 0.5\np.hue_rotation = 30.0"),
+            ("grade", ""),
+            ("keyer", ""),
+            ("luma_key", "p.low = 0.2"),
+            ("channel_key", "p.low_softness = 0.2"),
+            ("difference_key", "p.fg <- r"),
+            ("premult", ""),
+            ("unpremult", ""),
+            ("shuffle", "p.fg <- r"),
+        ];
+        for (kind, lines) in nodes.iter() {
+            let src = format!(
+                "{}
+                new ramp r
+                r.width = 64
+                r.height = 48
+                r.end_x = 64.0
+                new constant k
+                k.width = 3
+                k.height = 3
+                new {} p
+                p.bg <- n
+                {}
+                glob viewing = p",
+                NOISE, kind, lines
+            );
+            for region in &[rect(10, 7, 20, 18), rect(-4, 40, 30, 12)] {
+                assert_region_matches(&src, *region);
+            }
+        }
+    }
+
+    fn key(engine: &Engine, name: &str) -> Key {
+        let id = engine.node_names[name];
+        engine.keys(engine.viewing.unwrap()).unwrap()[&id]
+    }
+
+    #[test]
+    fn keys_follow_what_changes_output() {
+        let src = format!(
+            "{}
+            new blur b
+            b.bg <- n
+            glob viewing = b",
+            NOISE
+        );
+        let mut engine = comp(&src);
+        let (noise, blur) = (key(&engine, "n"), key(&engine, "b"));
+        let same = comp(&src);
+        assert_eq!((key(&same, "n"), key(&same, "b")), (noise, blur));
+
+        // Blur isn't animated, but its input is
+        engine.frame = 3;
+        assert_ne!(key(&engine, "n"), noise);
+        assert_ne!(key(&engine, "b"), blur);
+
+        let mut engine = comp(&src);
+        mdl::apply(&mut engine, &Parser::default().parse("b.size = 2").unwrap()).unwrap();
+        assert_eq!(key(&engine, "n"), noise);
+        assert_ne!(key(&engine, "b"), blur);
+
+        // Undoing finds the earlier output again
+        mdl::undo(&mut engine).unwrap();
+        assert_eq!(key(&engine, "b"), blur);
+    }
 }
@@ -0,0 +1,143 @@
+use crate::utils::Vec2U;
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
+
+// A frame size along with the shape of its pixels, given as
+// their width over their height. Anamorphic formats squeeze
+// the picture horizontally, so their pixels are wider.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Format {
+    pub width: usize,
+    pub height: usize,
+    pub pixel_aspect: f32,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self::new(1920, 1080, 1.0)
+    }
+}
+
+// The aspect hashes by its bits
+impl Hash for Format {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.width.hash(state);
+        self.height.hash(state);
+        self.pixel_aspect.to_bits().hash(state);
+    }
+}
+
+impl Format {
+    pub fn new(width: usize, height: usize, pixel_aspect: f32) -> Self {
+        Self {
+            width,
+            height,
+            pixel_aspect,
+        }
+    }
+
+    pub fn size(&self) -> Vec2U {
+        Vec2U::new(self.width, self.height)
+    }
+
+    // A custom format written as WIDTHxHEIGHT with an
+    // optional :ASPECT for the pixels, like "1828x1556:2"
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let err = || format!("Invalid format: {}", spec);
+        let (size, aspect) = match spec.split_once(':') {
+            Some((size, aspect)) => (size, aspect.parse::<f32>().map_err(|_| err())?),
+            None => (spec, 1.0),
+        };
+        let (width, height) = size.split_once('x').ok_or_else(err)?;
+        let width = width.parse::<usize>().map_err(|_| err())?;
+        let height = height.parse::<usize>().map_err(|_| err())?;
+        if width == 0 || height == 0 || aspect <= 0.0 {
+            return Err(err());
+        }
+        Ok(Self::new(width, height, aspect))
+    }
+}
+
+// Formats by name. Common ones are there from the start
+// and others can be added.
+#[derive(Debug, Clone)]
+pub struct Formats {
+    formats: HashMap<String, Format>,
+}
+
+impl Default for Formats {
+    fn default() -> Self {
+        let builtin = [
+            ("PAL", Format::new(720, 576, 1.0926)),
+            ("NTSC", Format::new(720, 486, 0.9091)),
+            ("HD_720", Format::new(1280, 720, 1.0)),
+            ("HD_1080", Format::new(1920, 1080, 1.0)),
+            ("UHD_4K", Format::new(3840, 2160, 1.0)),
+            ("UHD_8K", Format::new(7680, 4320, 1.0)),
+            ("DCI_2K", Format::new(2048, 1080, 1.0)),
+            ("DCI_2K_FLAT", Format::new(1998, 1080, 1.0)),
+            ("DCI_2K_SCOPE", Format::new(2048, 858, 1.0)),
+            ("DCI_4K", Format::new(4096, 2160, 1.0)),
+            ("SUPER_35_2K", Format::new(2048, 1556, 1.0)),
+            ("ANAMORPHIC_2K", Format::new(1828, 1556, 2.0)),
+        ];
+        Self {
+            formats: builtin
+                .iter()
+                .map(|(name, format)| (name.to_string(), *format))
+                .collect(),
+        }
+    }
+}
+
+impl Formats {
+    pub fn get(&self, name: &str) -> Option<Format> {
+        self.formats.get(name).copied()
+    }
+
+    pub fn insert(&mut self, name: String, format: Format) {
+        self.formats.insert(name, format);
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.formats.keys().map(String::as_str)
+    }
+
+    // Takes either the name of a format or a custom one
+    pub fn lookup(&self, spec: &str) -> Result<Format, String> {
+        match self.get(spec) {
+            Some(format) => Ok(format),
+            None if spec.contains('x') && spec.starts_with(|c: char| c.is_ascii_digit()) => {
+                Format::parse(spec)
+            }
+            None => Err(format!("Format not found: {}", spec)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_custom_formats() {
+        assert_eq!(Format::parse("640x480"), Ok(Format::new(640, 480, 1.0)));
+        assert_eq!(
+            Format::parse("1828x1556:2"),
+            Ok(Format::new(1828, 1556, 2.0))
+        );
+        for spec in &["640", "0x480", "640x480:0", "640x480:wide", "axb"] {
+            assert!(Format::parse(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn looks_up_names_or_sizes() {
+        let formats = Formats::default();
+        assert_eq!(formats.lookup("PAL").unwrap().pixel_aspect, 1.0926);
+        assert_eq!(formats.lookup("64x32").unwrap().size(), Vec2U::new(64, 32));
+        assert!(formats.lookup("PAL_WIDE").is_err());
+    }
+}
@@ -2,32 +2,33 @@ use crate::utils::Id;
 use petgraph::{
     algo::is_cyclic_directed,
     graph::{EdgeIndex, NodeIndex},
+    stable_graph::StableGraph,
     visit::EdgeRef,
+    Direction,
 };
+use std::collections::HashSet;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Node {
     pub plugin: Id,
-    pub dirty: bool,
 }
 
 impl Node {
     pub fn new(plugin: Id) -> Self {
-        Self {
-            plugin,
-            dirty: true,
-        }
+        Self { plugin }
     }
 }
 
-type Input = u8;
+pub type Input = u8;
 
+// Node ids are indices into the graph. Removing a node leaves
+// the others where they are, and its index free for reuse.
 #[derive(Debug, Clone, Default)]
-pub struct Graph(petgraph::Graph<(), Input>);
+pub struct Graph(StableGraph<(), Input>);
 
 impl Graph {
-    pub fn insert_node(&mut self) {
-        self.0.add_node(());
+    pub fn insert_node(&mut self) -> Id {
+        self.0.add_node(()).index()
     }
 
     pub fn delete_node(&mut self, id: Id) {
@@ -35,22 +36,43 @@ impl Graph {
         self.0.remove_node(index);
     }
 
+    // Replaces whatever was connected to the input,
+    // unless the new connection would form a cycle
     pub fn connect(&mut self, downstream: Id, upstream: Id, input: Input) {
+        let previous = self.input_edge(downstream, input);
         let upstream = NodeIndex::new(upstream);
         let downstream = NodeIndex::new(downstream);
         let edge = self.0.add_edge(downstream, upstream, input);
         if is_cyclic_directed(&self.0) {
             self.0.remove_edge(edge);
+        } else if let Some(previous) = previous {
+            self.0.remove_edge(previous);
         }
     }
 
     pub fn disconnect(&mut self, from: Id, input: Input) {
-        if let Some(input) = self.input(from, input) {
-            let index = EdgeIndex::new(input);
-            self.0.remove_edge(index);
+        if let Some(edge) = self.input_edge(from, input) {
+            self.0.remove_edge(edge);
         }
     }
 
+    fn input_edge(&self, node: Id, input: Input) -> Option<EdgeIndex> {
+        let index = NodeIndex::new(node);
+        self.0
+            .edges(index)
+            .find(|edge| *edge.weight() == input)
+            .map(|edge| edge.id())
+    }
+
+    // Nodes taking this one as an input, along with which input
+    pub fn consumers(&self, node: Id) -> Vec<(Id, Input)> {
+        let index = NodeIndex::new(node);
+        self.0
+            .edges_directed(index, Direction::Incoming)
+            .map(|edge| (edge.source().index(), *edge.weight()))
+            .collect()
+    }
+
     pub fn input(&self, node: Id, input: Input) -> Option<Id> {
         let index = NodeIndex::new(node);
         for edge in self.0.edges(index) {
@@ -62,10 +84,21 @@ impl Graph {
         None
     }
 
+    // Nodes upstream of the viewer, each listed after everything
+    // that consumes it. Rendering in reverse visits inputs first.
     pub fn render_queue(&self, viewing: Id) -> Vec<Id> {
         let mut queue = Vec::new();
         self.render_queue_recurse(viewing, &mut queue);
-        queue
+        // Shared inputs are reached more than once, and the
+        // last visit is the one that follows all consumers
+        let mut seen = HashSet::new();
+        let mut deduped = queue
+            .into_iter()
+            .rev()
+            .filter(|id| seen.insert(*id))
+            .collect::<Vec<_>>();
+        deduped.reverse();
+        deduped
     }
 
     fn render_queue_recurse(&self, node: Id, queue: &mut Vec<Id>) {
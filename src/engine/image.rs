@@ -1,4 +1,4 @@
-use crate::utils::{Vec2I, Vec2U};
+use crate::utils::{Rect, Vec2I, Vec2U};
 use rayon::{
     prelude::*,
     slice::{Iter as ParIter, IterMut as ParIterMut},
@@ -39,27 +39,34 @@ impl Channel {
         }
     }
 
+    pub fn filled(size: Vec2U, value: f32) -> Self {
+        Self {
+            size,
+            elements: vec![value; size.area()],
+        }
+    }
+
     pub fn size(&self) -> Vec2U {
         self.size
     }
 
-    pub fn elements(&self) -> StdIter<f32> {
+    pub fn elements(&self) -> StdIter<'_, f32> {
         self.elements.iter()
     }
 
-    pub fn elements_mut(&mut self) -> StdIterMut<f32> {
+    pub fn elements_mut(&mut self) -> StdIterMut<'_, f32> {
         self.elements.iter_mut()
     }
 
     // Callers should probably use .chunks() with this
     // or the step size will be too small for parallelism
     // to be an effective tool
-    pub fn par_elements(&self) -> ParIter<f32> {
+    pub fn par_elements(&self) -> ParIter<'_, f32> {
         self.elements.par_iter()
     }
 
     // Samesies
-    pub fn par_elements_mut(&mut self) -> ParIterMut<f32> {
+    pub fn par_elements_mut(&mut self) -> ParIterMut<'_, f32> {
         self.elements.par_iter_mut()
     }
 
@@ -72,20 +79,54 @@ impl Channel {
         }
     }
 
-    pub fn lines(&self) -> StdLines {
-        self.elements.chunks_exact(self.size.x)
+    // Empty channels have no lines, but chunking
+    // by a width of zero would panic regardless
+    pub fn lines(&self) -> StdLines<'_> {
+        self.elements.chunks_exact(self.size.x.max(1))
+    }
+
+    pub fn lines_mut(&mut self) -> StdLinesMut<'_> {
+        self.elements.chunks_exact_mut(self.size.x.max(1))
     }
 
-    pub fn lines_mut(&mut self) -> StdLinesMut {
-        self.elements.chunks_exact_mut(self.size.x)
+    pub fn par_lines(&self) -> ParLines<'_> {
+        self.elements.par_chunks(self.size.x.max(1))
     }
 
-    pub fn par_lines(&self) -> ParLines {
-        self.elements.par_chunks(self.size.x)
+    pub fn par_lines_mut(&mut self) -> ParLinesMut<'_> {
+        self.elements.par_chunks_mut(self.size.x.max(1))
     }
 
-    pub fn par_lines_mut(&mut self) -> ParLinesMut {
-        self.elements.par_chunks_mut(self.size.x)
+    // The part of a region inside the channel,
+    // where no region means the whole channel
+    pub fn clip(&self, region: Option<Rect>) -> Rect {
+        let bounds = Rect::from_size(self.size);
+        region.map_or(bounds, |region| region.intersect(bounds))
+    }
+
+    // The spans of each line that fall inside a region
+    pub fn par_region_lines(
+        &self,
+        region: Option<Rect>,
+    ) -> impl IndexedParallelIterator<Item = &[f32]> {
+        let region = self.clip(region);
+        let (rows, columns) = (region.rows(), region.columns());
+        self.par_lines()
+            .skip(rows.start)
+            .take(rows.len())
+            .map(move |line| &line[columns.clone()])
+    }
+
+    pub fn par_region_lines_mut(
+        &mut self,
+        region: Option<Rect>,
+    ) -> impl IndexedParallelIterator<Item = &mut [f32]> {
+        let region = self.clip(region);
+        let (rows, columns) = (region.rows(), region.columns());
+        self.par_lines_mut()
+            .skip(rows.start)
+            .take(rows.len())
+            .map(move |line| &mut line[columns.clone()])
     }
 }
 
@@ -139,9 +180,64 @@ impl rayon::iter::FromParallelIterator<f32> for ChannelBuilder {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+pub const RED: &str = "rgba.red";
+pub const GREEN: &str = "rgba.green";
+pub const BLUE: &str = "rgba.blue";
+pub const ALPHA: &str = "rgba.alpha";
+pub const RGBA: [&str; 4] = [RED, GREEN, BLUE, ALPHA];
+
+// Channel names take the form layer.channel, such as
+// rgba.red, depth.Z or N.x. Names without a dot are
+// their own layer.
+pub fn layer_of(name: &str) -> &str {
+    match name.find('.') {
+        Some(dot) => &name[..dot],
+        None => name,
+    }
+}
+
+// Names for channels that arrive without them, such as those
+// loaded from formats that only know about channel counts
+pub fn default_names(count: usize) -> Vec<String> {
+    match count {
+        1 => vec![RED.into()],
+        2 => vec![RED.into(), ALPHA.into()],
+        _ => (0..count)
+            .map(|i| match RGBA.get(i) {
+                Some(name) => name.to_string(),
+                None => format!("other.{}", i - RGBA.len()),
+            })
+            .collect(),
+    }
+}
+
+// Like EXR, pixels are stored for a data window that may be
+// larger or smaller than the frame, or display window. The
+// image is black and transparent outside the data window.
+#[derive(Debug, Clone)]
 pub struct Image {
     channels: Vec<Channel>,
+    names: Vec<String>,
+    // Whether color channels have been multiplied by alpha
+    premultiplied: bool,
+    // Where the first pixel of each channel sits in the frame
+    origin: Vec2I,
+    display: Rect,
+    // The width of each pixel over its height
+    pixel_aspect: f32,
+}
+
+impl Default for Image {
+    fn default() -> Self {
+        Self {
+            channels: Vec::new(),
+            names: Vec::new(),
+            premultiplied: false,
+            origin: Vec2I::default(),
+            display: Rect::default(),
+            pixel_aspect: 1.0,
+        }
+    }
 }
 
 impl Image {
@@ -149,6 +245,24 @@ impl Image {
         assert!(desc.channels > 0);
         Self {
             channels: vec![Channel::black(desc.size); desc.channels],
+            names: default_names(desc.channels),
+            premultiplied: false,
+            origin: Vec2I::default(),
+            display: Rect::from_size(desc.size),
+            pixel_aspect: 1.0,
+        }
+    }
+
+    fn from_channels(channels: Vec<Channel>, names: Vec<String>) -> Self {
+        assert!(channels_are_valid(&channels));
+        let display = Rect::from_size(channels[0].size());
+        Self {
+            channels,
+            names,
+            premultiplied: false,
+            origin: Vec2I::default(),
+            display,
+            pixel_aspect: 1.0,
         }
     }
 
@@ -164,21 +278,271 @@ impl Image {
         Desc::new(self.size(), self.channel_count())
     }
 
-    pub fn channels(&self) -> StdIter<Channel> {
+    pub fn channels(&self) -> StdIter<'_, Channel> {
         self.channels.iter()
     }
 
-    pub fn channels_mut(&mut self) -> StdIterMut<Channel> {
+    pub fn channels_mut(&mut self) -> StdIterMut<'_, Channel> {
         self.channels.iter_mut()
     }
 
-    pub fn par_channels(&self) -> ParIter<Channel> {
+    pub fn par_channels(&self) -> ParIter<'_, Channel> {
         self.channels.par_iter()
     }
 
-    pub fn par_channels_mut(&mut self) -> ParIterMut<Channel> {
+    pub fn par_channels_mut(&mut self) -> ParIterMut<'_, Channel> {
         self.channels.par_iter_mut()
     }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.names.iter().map(String::as_str)
+    }
+
+    pub fn name(&self, i: usize) -> &str {
+        &self.names[i]
+    }
+
+    pub fn set_name(&mut self, i: usize, name: String) {
+        self.names[i] = name;
+    }
+
+    pub fn index_of_channel(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    pub fn channel_by_name(&self, name: &str) -> Option<&Channel> {
+        self.index_of_channel(name).map(|i| &self.channels[i])
+    }
+
+    pub fn channel_by_name_mut(&mut self, name: &str) -> Option<&mut Channel> {
+        match self.index_of_channel(name) {
+            Some(i) => Some(&mut self.channels[i]),
+            None => None,
+        }
+    }
+
+    // Layer names in the order they first appear
+    pub fn layers(&self) -> Vec<&str> {
+        let mut layers = Vec::new();
+        for name in self.names() {
+            let layer = layer_of(name);
+            if !layers.contains(&layer) {
+                layers.push(layer);
+            }
+        }
+        layers
+    }
+
+    pub fn layer(&self, layer: &str) -> Vec<usize> {
+        self.names()
+            .enumerate()
+            .filter(|(_, name)| layer_of(name) == layer)
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    // Adds a channel or replaces the one with the same name
+    pub fn insert_channel(&mut self, name: String, channel: Channel) {
+        assert!(channel.size() == self.size());
+        match self.index_of_channel(&name) {
+            Some(i) => self.channels[i] = channel,
+            None => {
+                self.channels.push(channel);
+                self.names.push(name);
+            }
+        }
+    }
+
+    pub fn alpha_index(&self) -> Option<usize> {
+        self.index_of_channel(ALPHA)
+    }
+
+    // Red, green and blue where present
+    pub fn color_indices(&self) -> Vec<usize> {
+        RGBA[..3]
+            .iter()
+            .filter_map(|name| self.index_of_channel(name))
+            .collect()
+    }
+
+    pub fn rgb(&self) -> Option<[&Channel; 3]> {
+        Some([
+            self.channel_by_name(RED)?,
+            self.channel_by_name(GREEN)?,
+            self.channel_by_name(BLUE)?,
+        ])
+    }
+
+    pub fn rgb_mut(&mut self) -> Option<[&mut Channel; 3]> {
+        let indices = [
+            self.index_of_channel(RED)?,
+            self.index_of_channel(GREEN)?,
+            self.index_of_channel(BLUE)?,
+        ];
+        self.channels.get_disjoint_mut(indices).ok()
+    }
+
+    // Takes on the alpha state, display window and pixel aspect of another
+    // image, as well as its channel names when the channel
+    // count matches and its data window when the size does
+    pub fn copy_attributes(&mut self, src: &Image) {
+        if src.channel_count() == self.channel_count() {
+            self.names = src.names.clone();
+        }
+        if src.size() == self.size() {
+            self.origin = src.origin;
+        }
+        self.premultiplied = src.premultiplied;
+        self.display = src.display;
+        self.pixel_aspect = src.pixel_aspect;
+    }
+
+    pub fn data_window(&self) -> Rect {
+        Rect::new(self.origin, self.origin + self.size().into())
+    }
+
+    pub fn set_data_origin(&mut self, origin: Vec2I) {
+        self.origin = origin;
+    }
+
+    pub fn display_window(&self) -> Rect {
+        self.display
+    }
+
+    pub fn set_display_window(&mut self, display: Rect) {
+        self.display = display;
+    }
+
+    pub fn pixel_aspect(&self) -> f32 {
+        self.pixel_aspect
+    }
+
+    pub fn set_pixel_aspect(&mut self, pixel_aspect: f32) {
+        self.pixel_aspect = pixel_aspect;
+    }
+
+    // Converts a region of the frame to channel coordinates
+    pub fn local_region(&self, region: Option<Rect>) -> Option<Rect> {
+        region.map(|region| region.translate(-self.origin))
+    }
+
+    // Copies the pixels into a new data window,
+    // filling in black where there were none
+    pub fn reframed(&self, data: Rect) -> Image {
+        let overlap = data.intersect(self.data_window());
+        let src_columns = overlap.translate(-self.origin).columns();
+        let dst_columns = overlap.translate(-data.min).columns();
+        let mut out = self
+            .par_channels()
+            .map(|channel| {
+                let mut out = Channel::black(data.size());
+                if overlap.is_empty() {
+                    return out;
+                }
+                out.par_lines_mut().enumerate().for_each(|(y, line)| {
+                    let frame_y = y as isize + data.min.y;
+                    if frame_y < overlap.min.y || frame_y >= overlap.max.y {
+                        return;
+                    }
+                    let src_y = (frame_y - self.origin.y) as usize;
+                    let src = &channel.elements[src_y * channel.size.x..][src_columns.clone()];
+                    line[dst_columns.clone()].copy_from_slice(src);
+                });
+                out
+            })
+            .collect::<Image>();
+        out.names = self.names.clone();
+        out.premultiplied = self.premultiplied;
+        out.origin = data.min;
+        out.display = self.display;
+        out.pixel_aspect = self.pixel_aspect;
+        out
+    }
+
+    // A copy of the part within a region, or all of it for None
+    pub fn clone_region(&self, region: Option<Rect>) -> Image {
+        let data = self.data_window();
+        match region.map(|region| data.intersect(region)) {
+            Some(kept) if kept != data => self.reframed(kept),
+            _ => self.clone(),
+        }
+    }
+
+    pub fn is_premultiplied(&self) -> bool {
+        self.premultiplied
+    }
+
+    // Only records the state, see premultiply
+    // and unpremultiply to change the pixels
+    pub fn set_premultiplied(&mut self, premultiplied: bool) {
+        self.premultiplied = premultiplied;
+    }
+
+    pub fn premultiply(&mut self) {
+        if !self.premultiplied {
+            self.apply_alpha(|color, alpha| color * alpha);
+            self.premultiplied = true;
+        }
+    }
+
+    pub fn unpremultiply(&mut self) {
+        if self.premultiplied {
+            self.apply_alpha(|color, alpha| {
+                if alpha.abs() > f32::EPSILON {
+                    color / alpha
+                } else {
+                    color
+                }
+            });
+            self.premultiplied = false;
+        }
+    }
+
+    // Filters must work on premultiplied color or transparent pixels
+    // bleed into their neighbors. This runs a filter on premultiplied
+    // data and returns the result in the same state as the source.
+    pub fn map_premultiplied<F>(&self, filter: F) -> Image
+    where
+        F: FnOnce(&Image) -> Image,
+    {
+        let mut out = if self.premultiplied || self.alpha_index().is_none() {
+            filter(self)
+        } else {
+            let mut premultiplied = self.clone();
+            premultiplied.premultiply();
+            filter(&premultiplied)
+        };
+        out.copy_attributes(self);
+        out.premultiplied = true;
+        if !self.premultiplied {
+            out.unpremultiply();
+        }
+        out
+    }
+
+    // Applies to every channel other than alpha so
+    // that all layers share the same alpha state
+    fn apply_alpha(&mut self, op: fn(f32, f32) -> f32) {
+        let alpha = match self.alpha_index() {
+            Some(alpha) => self.channels[alpha].clone(),
+            None => return,
+        };
+        let names = &self.names;
+        self.channels
+            .par_iter_mut()
+            .zip(names.par_iter())
+            .filter(|(_, name)| name.as_str() != ALPHA)
+            .for_each(|(channel, _)| {
+                channel
+                    .par_lines_mut()
+                    .zip(alpha.par_lines())
+                    .for_each(|(line, alpha)| {
+                        for (color, alpha) in line.iter_mut().zip(alpha.iter()) {
+                            *color = op(*color, *alpha);
+                        }
+                    })
+            });
+    }
 }
 
 impl Index<usize> for Image {
@@ -201,8 +565,8 @@ impl std::iter::FromIterator<Channel> for Image {
         I: IntoIterator<Item = Channel>,
     {
         let channels = src.into_iter().collect::<Vec<_>>();
-        assert!(channels_are_valid(&channels));
-        Self { channels }
+        let names = default_names(channels.len());
+        Self::from_channels(channels, names)
     }
 }
 
@@ -212,8 +576,18 @@ impl rayon::iter::FromParallelIterator<Channel> for Image {
         I: IntoParallelIterator<Item = Channel>,
     {
         let channels = src.into_par_iter().collect::<Vec<_>>();
-        assert!(channels_are_valid(&channels));
-        Self { channels }
+        let names = default_names(channels.len());
+        Self::from_channels(channels, names)
+    }
+}
+
+impl std::iter::FromIterator<(String, Channel)> for Image {
+    fn from_iter<I>(src: I) -> Self
+    where
+        I: IntoIterator<Item = (String, Channel)>,
+    {
+        let (names, channels): (Vec<_>, Vec<_>) = src.into_iter().unzip();
+        Self::from_channels(channels, names)
     }
 }
 
@@ -1,5 +1,8 @@
+pub mod cache;
+pub mod color;
 pub mod control;
 pub mod engine;
+pub mod format;
 pub mod graph;
 pub mod image;
 pub mod mdl;
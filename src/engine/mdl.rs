@@ -4,6 +4,9 @@ use std::{collections::HashMap, fmt, iter::Peekable, slice::Iter, str::Chars};
 mod unpack;
 pub use unpack::apply;
 
+mod history;
+pub use history::{redo, undo, History};
+
 type TokenMap = HashMap<String, Token>;
 type Tokens<'a> = Peekable<Iter<'a, Token>>;
 
@@ -20,6 +23,16 @@ pub enum Statement {
     Delete {
         name: String,
     },
+    Rename {
+        old: String,
+        new: String,
+    },
+    // Copies are named in order, or by prefixing the source names
+    Copy {
+        sources: Vec<String>,
+        targets: Vec<String>,
+        wired: bool,
+    },
     Glob {
         attr: String,
         value: Literal,
@@ -28,6 +41,13 @@ pub enum Statement {
         downstream: Member,
         upstream: String,
     },
+    Unwire {
+        downstream: Member,
+    },
+    Undo,
+    Redo,
+    Begin,
+    Commit,
 }
 
 #[derive(Debug, Clone)]
@@ -60,6 +80,15 @@ enum Token {
     Glob,
     New,
     Delete,
+    Rename,
+    Copy,
+    As,
+    Wired,
+    Undo,
+    Redo,
+    Begin,
+    Commit,
+    Nothing,
 }
 
 #[derive(Debug, Clone)]
@@ -68,15 +97,15 @@ pub enum ParseError {
     Message(String),
 }
 
-impl Into<ParseError> for &str {
-    fn into(self) -> ParseError {
-        ParseError::Message(self.into())
+impl From<&str> for ParseError {
+    fn from(message: &str) -> Self {
+        ParseError::Message(message.into())
     }
 }
 
-impl Into<ParseError> for String {
-    fn into(self) -> ParseError {
-        ParseError::Message(self)
+impl From<String> for ParseError {
+    fn from(message: String) -> Self {
+        ParseError::Message(message)
     }
 }
 
@@ -92,7 +121,6 @@ impl fmt::Display for ParseError {
 #[derive(Debug, Clone)]
 pub struct Parser {
     keywords: TokenMap,
-    tokens: Vec<Token>,
 }
 
 impl Default for Parser {
@@ -101,18 +129,24 @@ impl Default for Parser {
         keywords.insert("glob".into(), Token::Glob);
         keywords.insert("new".into(), Token::New);
         keywords.insert("delete".into(), Token::Delete);
+        keywords.insert("rename".into(), Token::Rename);
+        keywords.insert("copy".into(), Token::Copy);
+        keywords.insert("as".into(), Token::As);
+        keywords.insert("wired".into(), Token::Wired);
+        keywords.insert("undo".into(), Token::Undo);
+        keywords.insert("redo".into(), Token::Redo);
+        keywords.insert("begin".into(), Token::Begin);
+        keywords.insert("commit".into(), Token::Commit);
+        keywords.insert("none".into(), Token::Nothing);
         keywords.insert("true".into(), Token::Value(Value::Boolean(true)));
         keywords.insert("false".into(), Token::Value(Value::Boolean(false)));
-        Self {
-            keywords,
-            tokens: Vec::new(),
-        }
+        Self { keywords }
     }
 }
 
 impl Parser {
     pub fn parse(&self, src: &str) -> Result<Statement, ParseError> {
-        let tokens = tokenize(src, &self.keywords).map_err(|e| e.into())?;
+        let tokens = tokenize(src, &self.keywords).map_err(ParseError::from)?;
         parse(&tokens)
     }
 }
@@ -136,7 +170,11 @@ fn tokenize(src: &str, keywords: &TokenMap) -> Result<Vec<Token>, String> {
             }
             c => {
                 if c.is_ascii_alphabetic() {
-                    let text = consume(&mut iter, |c| c.is_ascii_alphabetic() || c == '_', Some(c));
+                    let text = consume(
+                        &mut iter,
+                        |c| c.is_ascii_alphanumeric() || c == '_',
+                        Some(c),
+                    );
                     match keywords.get(&text) {
                         Some(token) => Ok(token.clone()),
                         None => Ok(Token::Identifier(text)),
@@ -161,7 +199,11 @@ fn tokenize(src: &str, keywords: &TokenMap) -> Result<Vec<Token>, String> {
     Ok(tokens)
 }
 
-fn consume(iter: &mut Peekable<Chars>, matcher: fn(char) -> bool, seed: Option<char>) -> String {
+fn consume(
+    iter: &mut Peekable<Chars<'_>>,
+    matcher: fn(char) -> bool,
+    seed: Option<char>,
+) -> String {
     let mut text = String::new();
     if let Some(seed) = seed {
         text.push(seed);
@@ -178,12 +220,28 @@ fn consume(iter: &mut Peekable<Chars>, matcher: fn(char) -> bool, seed: Option<c
 
 // TODO: Better help for incorrect statements
 fn parse(tokens: &[Token]) -> Result<Statement, ParseError> {
+    // None only stands in for the upstream node of an unwire
+    let names_none = match tokens {
+        [init @ .., Token::Arrow, Token::Nothing] => init.iter().any(is_nothing),
+        _ => tokens.iter().any(is_nothing),
+    };
+    if names_none {
+        return Err("none is reserved for unwiring and can't name a node".into());
+    }
     let mut iter = tokens.iter().peekable();
     match iter.peek() {
         Some(token) => match token {
             Token::Glob => glob(&mut iter).map_err(|_| "Invalid glob".into()),
             Token::New => new(&mut iter).map_err(|_| "Invalid new".into()),
             Token::Delete => delete(&mut iter).map_err(|_| "Invalid delete".into()),
+            Token::Rename => rename(&mut iter).map_err(|_| "Invalid rename".into()),
+            Token::Copy => copy(&mut iter).map_err(|_| "Invalid copy".into()),
+            Token::Undo => bare(&mut iter, Statement::Undo).map_err(|_| "Invalid undo".into()),
+            Token::Redo => bare(&mut iter, Statement::Redo).map_err(|_| "Invalid redo".into()),
+            Token::Begin => bare(&mut iter, Statement::Begin).map_err(|_| "Invalid begin".into()),
+            Token::Commit => {
+                bare(&mut iter, Statement::Commit).map_err(|_| "Invalid commit".into())
+            }
             Token::Identifier(_) => set(&mut iter).map_err(|e| e.into()),
             _ => Err("Unrecognized statement".into()),
         },
@@ -191,6 +249,10 @@ fn parse(tokens: &[Token]) -> Result<Statement, ParseError> {
     }
 }
 
+fn is_nothing(token: &Token) -> bool {
+    matches!(token, Token::Nothing)
+}
+
 fn glob(iter: &mut Tokens) -> Result<Statement, ()> {
     iter.next();
     let attr = ident(iter)?;
@@ -212,6 +274,62 @@ fn delete(iter: &mut Tokens) -> Result<Statement, ()> {
     Ok(Statement::Delete { name })
 }
 
+fn rename(iter: &mut Tokens) -> Result<Statement, ()> {
+    iter.next();
+    let old = ident(iter)?;
+    let new = ident(iter)?;
+    Ok(Statement::Rename { old, new })
+}
+
+// Either copy src dst, or copy a b c as prefix,
+// optionally followed by wired
+fn copy(iter: &mut Tokens) -> Result<Statement, ()> {
+    iter.next();
+    let mut sources = Vec::new();
+    while let Some(Token::Identifier(name)) = iter.peek() {
+        sources.push(name.clone());
+        iter.next();
+    }
+    let targets = match iter.peek() {
+        Some(Token::As) => {
+            iter.next();
+            let prefix = ident(iter)?;
+            sources
+                .iter()
+                .map(|name| format!("{}{}", prefix, name))
+                .collect()
+        }
+        _ => match sources.len() {
+            2 => vec![sources.pop().ok_or(())?],
+            _ => return Err(()),
+        },
+    };
+    if sources.is_empty() {
+        return Err(());
+    }
+    let wired = matches!(iter.peek(), Some(Token::Wired));
+    if wired {
+        iter.next();
+    }
+    match iter.next() {
+        Some(_) => Err(()),
+        None => Ok(Statement::Copy {
+            sources,
+            targets,
+            wired,
+        }),
+    }
+}
+
+// Statements made of a keyword alone
+fn bare(iter: &mut Tokens, statement: Statement) -> Result<Statement, ()> {
+    iter.next();
+    match iter.next() {
+        Some(_) => Err(()),
+        None => Ok(statement),
+    }
+}
+
 fn set(iter: &mut Tokens) -> Result<Statement, String> {
     let member = member(iter).map_err(|_| "Unrecognized member".to_string())?;
     match iter.next() {
@@ -227,6 +345,9 @@ fn assign(iter: &mut Tokens, member: Member) -> Result<Statement, ()> {
 }
 
 fn wire(iter: &mut Tokens, downstream: Member) -> Result<Statement, ()> {
+    if let Some(Token::Nothing) = iter.peek() {
+        return Ok(Statement::Unwire { downstream });
+    }
     let upstream = ident(iter)?;
     Ok(Statement::Wire {
         upstream,
@@ -276,3 +397,41 @@ fn ident(iter: &mut Tokens) -> Result<String, ()> {
         _ => Err(()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(src: &str) -> Result<Statement, String> {
+        Parser::default().parse(src).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn unwire_with_none() {
+        match parse("combine.fg <- none") {
+            Ok(Statement::Unwire { downstream }) => {
+                assert_eq!(downstream.to_string(), "combine.fg")
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(
+            parse("combine.fg <- nonesuch"),
+            Ok(Statement::Wire { .. })
+        ));
+    }
+
+    #[test]
+    fn none_is_not_a_name() {
+        for src in &[
+            "new blur none",
+            "none.size = 2",
+            "none.bg <- none",
+            "rename blur none",
+            "copy blur none",
+            "glob viewing = none",
+        ] {
+            let error = parse(src).unwrap_err();
+            assert!(error.contains("reserved"), "{}: {}", src, error);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use super::{
+    unpack::{desc, perform},
+    *,
+};
+use crate::engine::Engine;
+use std::mem;
+
+// A statement along with those that revert it
+#[derive(Debug, Clone)]
+pub(super) struct Change {
+    statement: Statement,
+    inverse: Vec<Statement>,
+}
+
+// Changes undone and redone together
+type Transaction = Vec<Change>;
+
+// Edits to the graph, kept so they can be undone. Statements
+// between begin and commit form a single transaction.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+    open: Transaction,
+    // Begins without a matching commit, so transactions can nest
+    depth: usize,
+}
+
+impl History {
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn begin(&mut self) {
+        self.depth += 1;
+    }
+
+    pub fn commit(&mut self) -> Result<(), String> {
+        if self.depth == 0 {
+            return Err("No transaction to commit".to_string());
+        }
+        self.depth -= 1;
+        if self.depth == 0 && !self.open.is_empty() {
+            self.undo.push(mem::take(&mut self.open));
+        }
+        Ok(())
+    }
+
+    pub(super) fn record(&mut self, change: Change) {
+        self.redo.clear();
+        if self.depth > 0 {
+            self.open.push(change);
+        } else {
+            self.undo.push(vec![change]);
+        }
+    }
+}
+
+// Transactions apply whole or not at all, so if one fails
+// partway it is rolled back and left where it was
+pub fn undo(engine: &mut Engine) -> Result<(), String> {
+    if engine.history.depth > 0 {
+        return Err("Commit the open transaction before undoing".to_string());
+    }
+    let transaction = engine.history.undo.pop().ok_or("Nothing to undo")?;
+    let inverses = transaction
+        .iter()
+        .rev()
+        .flat_map(|change| change.inverse.iter());
+    match perform_all(engine, inverses) {
+        Ok(()) => engine.history.redo.push(transaction),
+        Err(e) => {
+            engine.history.undo.push(transaction);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+pub fn redo(engine: &mut Engine) -> Result<(), String> {
+    if engine.history.depth > 0 {
+        return Err("Commit the open transaction before redoing".to_string());
+    }
+    let transaction = engine.history.redo.pop().ok_or("Nothing to redo")?;
+    let statements = transaction.iter().map(|change| &change.statement);
+    match perform_all(engine, statements) {
+        Ok(()) => engine.history.undo.push(transaction),
+        Err(e) => {
+            engine.history.redo.push(transaction);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+// Performs the statements in order. Should one fail, those
+// already performed are reverted, newest first.
+fn perform_all<'a>(
+    engine: &mut Engine,
+    statements: impl Iterator<Item = &'a Statement>,
+) -> Result<(), String> {
+    let mut applied: Vec<Change> = Vec::new();
+    for statement in statements {
+        let reverted = change(engine, statement);
+        if let Err(e) = perform(engine, statement) {
+            for change in applied.iter().rev() {
+                for statement in change.inverse.iter() {
+                    // Carry on so that as much as possible is put back
+                    let _ = perform(engine, statement);
+                }
+            }
+            return Err(e);
+        }
+        applied.extend(reverted);
+    }
+    Ok(())
+}
+
+// Captures what a statement is about to change. None for statements
+// that leave the graph alone or that will fail to apply.
+pub(super) fn change(engine: &Engine, statement: &Statement) -> Option<Change> {
+    let inverse = match statement {
+        Statement::Assign { member, .. } => {
+            let id = *engine.node_names.get(&member.node)?;
+            let index = desc(engine, id)?.index_for_control(&member.attr)?;
+            let value = engine.controls.get(&id)?.get(index)?.clone();
+            vec![Statement::Assign {
+                member: member.clone(),
+                value,
+            }]
+        }
+
+        Statement::New { name, .. } => vec![Statement::Delete { name: name.clone() }],
+
+        // Brings back the node with its controls and wiring
+        Statement::Delete { name } => {
+            let id = *engine.node_names.get(name)?;
+            let plugin = desc(engine, id)?;
+            let mut inverse = vec![Statement::New {
+                kind: plugin.name().to_string(),
+                name: name.clone(),
+            }];
+            for (i, value) in engine.controls.get(&id)?.iter().enumerate() {
+                inverse.push(Statement::Assign {
+                    member: member(name, plugin.control_name(i)?),
+                    value: value.clone(),
+                });
+            }
+            for input in 0..plugin.inputs_len() {
+                if let Some(upstream) = engine.graph.input(id, input as u8) {
+                    inverse.push(Statement::Wire {
+                        downstream: member(name, plugin.input_name(input)?),
+                        upstream: engine.node_name(upstream)?.to_string(),
+                    });
+                }
+            }
+            for (consumer, input) in engine.graph.consumers(id) {
+                let attr = desc(engine, consumer)?.input_name(input as usize)?;
+                inverse.push(Statement::Wire {
+                    downstream: member(engine.node_name(consumer)?, attr),
+                    upstream: name.clone(),
+                });
+            }
+            if engine.viewing == Some(id) {
+                inverse.push(Statement::Glob {
+                    attr: "viewing".to_string(),
+                    value: Literal::Identifier(name.clone()),
+                });
+            }
+            inverse
+        }
+
+        Statement::Rename { old, new } => vec![Statement::Rename {
+            old: new.clone(),
+            new: old.clone(),
+        }],
+
+        Statement::Copy { targets, .. } => targets
+            .iter()
+            .map(|name| Statement::Delete { name: name.clone() })
+            .collect(),
+
+        Statement::Wire { downstream, .. } | Statement::Unwire { downstream } => {
+            let id = *engine.node_names.get(&downstream.node)?;
+            let input = desc(engine, id)?.index_for_input(&downstream.attr)?;
+            match engine.graph.input(id, input as u8) {
+                Some(upstream) => vec![Statement::Wire {
+                    downstream: downstream.clone(),
+                    upstream: engine.node_name(upstream)?.to_string(),
+                }],
+                None => vec![Statement::Unwire {
+                    downstream: downstream.clone(),
+                }],
+            }
+        }
+
+        _ => return None,
+    };
+    Some(Change {
+        statement: statement.clone(),
+        inverse,
+    })
+}
+
+fn member(node: &str, attr: &str) -> Member {
+    Member {
+        node: node.to_string(),
+        attr: attr.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mdl::{apply, Parser},
+        utils::Id,
+    };
+
+    fn run(engine: &mut Engine, src: &str) -> Result<Option<String>, String> {
+        let statement = Parser::default().parse(src).map_err(|e| e.to_string())?;
+        apply(engine, &statement)
+    }
+
+    fn input(engine: &Engine, node: &str, input: u8) -> Option<Id> {
+        engine.graph.input(engine.node_names[node], input)
+    }
+
+    #[test]
+    fn undo_and_redo_edits() {
+        let mut engine = Engine::new();
+        for src in &["new constant c", "c.red = 0.5", "new blur b", "b.bg <- c"] {
+            run(&mut engine, src).unwrap();
+        }
+        let c = engine.node_names["c"];
+        assert_eq!(input(&engine, "b", 0), Some(c));
+
+        undo(&mut engine).unwrap();
+        assert_eq!(input(&engine, "b", 0), None);
+        undo(&mut engine).unwrap();
+        assert!(!engine.node_names.contains_key("b"));
+        undo(&mut engine).unwrap();
+        assert_eq!(engine.controls[&c][2].as_real(), 0.0);
+
+        for _ in 0..3 {
+            redo(&mut engine).unwrap();
+        }
+        assert_eq!(engine.controls[&c][2].as_real(), 0.5);
+        assert_eq!(input(&engine, "b", 0), Some(c));
+        assert!(!engine.history.can_redo());
+    }
+
+    #[test]
+    fn undo_brings_back_deleted_wiring() {
+        let mut engine = Engine::new();
+        for src in &[
+            "new constant c",
+            "new blur b",
+            "b.bg <- c",
+            "glob viewing = c",
+            "delete c",
+        ] {
+            run(&mut engine, src).unwrap();
+        }
+        undo(&mut engine).unwrap();
+        let c = engine.node_names["c"];
+        assert_eq!(input(&engine, "b", 0), Some(c));
+        assert_eq!(engine.viewing, Some(c));
+    }
+
+    #[test]
+    fn transaction_undoes_together() {
+        let mut engine = Engine::new();
+        for src in &[
+            "begin",
+            "new constant a",
+            "new blur b",
+            "b.bg <- a",
+            "commit",
+        ] {
+            run(&mut engine, src).unwrap();
+        }
+        undo(&mut engine).unwrap();
+        assert!(engine.node_names.is_empty());
+        assert!(!engine.history.can_undo());
+        redo(&mut engine).unwrap();
+        assert_eq!(input(&engine, "b", 0), Some(engine.node_names["a"]));
+    }
+
+    #[test]
+    fn failed_undo_rolls_back() {
+        let mut engine = Engine::new();
+        for src in &["begin", "new constant a", "new blur b", "commit"] {
+            run(&mut engine, src).unwrap();
+        }
+        // Renamed behind the history's back, so deleting a fails
+        // after b has already been deleted
+        let a = engine.node_names["a"];
+        engine.rename_node(a, "z".to_string()).unwrap();
+        assert!(undo(&mut engine).is_err());
+        assert!(engine.node_names.contains_key("b"));
+        assert!(engine.history.can_undo());
+    }
+
+    #[test]
+    fn new_rejects_taken_name() {
+        let mut engine = Engine::new();
+        run(&mut engine, "new constant a").unwrap();
+        let a = engine.node_names["a"];
+        assert!(run(&mut engine, "new blur a").is_err());
+        assert_eq!(engine.node_names["a"], a);
+        assert_eq!(engine.nodes.len(), 1);
+
+        undo(&mut engine).unwrap();
+        assert!(engine.node_names.is_empty());
+        assert!(engine.nodes.is_empty());
+    }
+}
@@ -1,7 +1,22 @@
-use super::*;
-use crate::{engine::Engine, graph::Node};
+use super::{history, *};
+use crate::{color, engine::Engine, graph::Node, plugin, utils::Id};
+use std::path::Path;
 
-pub fn apply(engine: &mut Engine, statement: &Statement) -> Result<(), String> {
+// Edits to the graph are recorded so they can be undone. Some
+// statements give a message worth passing on to the user.
+pub fn apply(engine: &mut Engine, statement: &Statement) -> Result<Option<String>, String> {
+    let change = history::change(engine, statement);
+    let message = perform(engine, statement)?;
+    if let Some(change) = change {
+        engine.history.record(change);
+    }
+    Ok(message)
+}
+
+pub(super) fn perform(
+    engine: &mut Engine,
+    statement: &Statement,
+) -> Result<Option<String>, String> {
     match statement {
         Statement::Assign { member, value } => {
             let node_id = match engine.node_names.get(&member.node) {
@@ -41,17 +56,22 @@ pub fn apply(engine: &mut Engine, statement: &Statement) -> Result<(), String> {
                 }
                 None => unreachable!(),
             }
-            Ok(())
+            Ok(None)
         }
 
         Statement::New { kind, name } => {
+            // Undoing the new deletes by name, which would
+            // leave a node it replaced with none
+            if engine.node_names.contains_key(name) {
+                return Err(format!("Node name already taken: {}", name));
+            }
             let plugin_id = match engine.plugin_names.get(kind) {
                 Some(id) => Ok(id),
                 None => Err(format!("Node kind not found: {}", kind)),
             }?;
             let node = Node::new(*plugin_id);
             engine.insert_node(node, name.into());
-            Ok(())
+            Ok(None)
         }
 
         Statement::Delete { name } => {
@@ -59,24 +79,114 @@ pub fn apply(engine: &mut Engine, statement: &Statement) -> Result<(), String> {
                 Some(id) => Ok(*id),
                 None => Err(format!("Node name not found: {}", name)),
             }?;
-            engine.delete_node(id);
-            Ok(())
+            let disconnected = engine
+                .delete_node(id)?
+                .into_iter()
+                .filter_map(|(consumer, input)| {
+                    let attr = desc(engine, consumer)?.input_name(input as usize)?;
+                    Some(format!("{}.{}", engine.node_name(consumer)?, attr))
+                })
+                .collect::<Vec<_>>();
+            if disconnected.is_empty() {
+                Ok(None)
+            } else {
+                Ok(Some(format!("Disconnected {}", disconnected.join(", "))))
+            }
+        }
+
+        Statement::Rename { old, new } => {
+            let id = match engine.node_names.get(old) {
+                Some(id) => Ok(*id),
+                None => Err(format!("Node name not found: {}", old)),
+            }?;
+            engine.rename_node(id, new.clone())?;
+            Ok(None)
+        }
+
+        Statement::Copy {
+            sources,
+            targets,
+            wired,
+        } => {
+            let ids = sources
+                .iter()
+                .map(|name| match engine.node_names.get(name) {
+                    Some(id) => Ok(*id),
+                    None => Err(format!("Node name not found: {}", name)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            engine.copy_nodes(&ids, targets, *wired)?;
+            Ok(None)
         }
 
         Statement::Glob { attr, value } => match attr.as_str() {
             "viewing" => match value {
                 Literal::Identifier(name) => match engine.node_names.get(name) {
                     Some(id) => {
-                        engine.viewing = *id;
-                        Ok(())
+                        engine.viewing = Some(*id);
+                        Ok(None)
                     }
                     None => Err(format!("Node name not found: {}", name)),
                 },
                 _ => Err("Viewing attribute takes a node identifier".to_string()),
             },
+            "frame" => match value {
+                Literal::Value(Value::Integer(frame)) => {
+                    engine.frame = *frame;
+                    Ok(None)
+                }
+                _ => Err("Frame attribute takes an integer".to_string()),
+            },
+            // Either a name like HD_1080 or a custom size like "1828x1556:2"
+            "format" => match value {
+                Literal::Identifier(spec) | Literal::Value(Value::Text(spec)) => {
+                    engine.format = engine.formats.lookup(spec)?;
+                    Ok(None)
+                }
+                _ => Err("Format attribute takes a format name or size".to_string()),
+            },
+            "color_config" => match value {
+                Literal::Value(Value::Text(path)) => {
+                    engine.colors = color::Config::load(Path::new(path))?;
+                    Ok(None)
+                }
+                _ => Err("Color config attribute takes a file path".to_string()),
+            },
             _ => Err(format!("Unrecognized global attribute: {}", attr)),
         },
 
+        Statement::Unwire { downstream } => {
+            let downstream_id = match engine.node_names.get(&downstream.node) {
+                Some(id) => Ok(id),
+                None => Err(format!(
+                    "Downstream node name not found: {}",
+                    downstream.node
+                )),
+            }?;
+            let downstream_node = match engine.nodes.get(downstream_id) {
+                Some(node) => node,
+                None => unreachable!(),
+            };
+            let downstream_plugin = match engine.plugins.get(&downstream_node.plugin) {
+                Some(plugin) => plugin,
+                None => unreachable!(),
+            };
+            let input = match downstream_plugin.desc().index_for_input(&downstream.attr) {
+                Some(index) => Ok(index),
+                None => Err(format!("Input name not found: {}", downstream.attr)),
+            }?;
+            engine.graph.disconnect(*downstream_id, input as u8);
+            Ok(None)
+        }
+
+        Statement::Undo => history::undo(engine).map(|_| None),
+        Statement::Redo => history::redo(engine).map(|_| None),
+        Statement::Begin => {
+            engine.history.begin();
+            Ok(None)
+        }
+        Statement::Commit => engine.history.commit().map(|_| None),
+
         Statement::Wire {
             downstream,
             upstream,
@@ -107,7 +217,12 @@ pub fn apply(engine: &mut Engine, statement: &Statement) -> Result<(), String> {
             engine
                 .graph
                 .connect(*downstream_id, *upstream_id, input as u8);
-            Ok(())
+            Ok(None)
         }
     }
 }
+
+pub(super) fn desc(engine: &Engine, id: Id) -> Option<&plugin::Desc> {
+    let node = engine.nodes.get(&id)?;
+    engine.plugins.get(&node.plugin).map(|plugin| plugin.desc())
+}
@@ -1,8 +1,9 @@
 use crate::{
-    control,
+    color, control,
+    format::{Format, Formats},
     image::Image,
     plugin,
-    utils::{Enumeration, Value},
+    utils::{Enumeration, Rect, Value},
 };
 
 mod plugins;
@@ -14,7 +15,28 @@ pub mod builtin;
 
 pub type Inputs<'a> = &'a [Option<&'a Image>];
 pub type Controls<'a> = &'a [Value];
-pub type Render = fn(Inputs, Controls) -> Result<Image, String>;
+pub type Render = fn(Inputs, Controls, &Context) -> Result<Image, String>;
+
+// The region of an input needed to render a region of the
+// output. None asks for the whole input, such as when the
+// mapping depends on image sizes that aren't known yet.
+pub type Roi = fn(usize, Rect, Controls) -> Option<Rect>;
+
+// Engine state available to plugins while rendering
+pub struct Context<'a> {
+    pub colors: &'a color::Config,
+    pub luts: &'a color::lut::Cache,
+    // The output pixels that are needed, or None for all of
+    // them. Pixels outside the region may be left undefined.
+    pub region: Option<Rect>,
+    // Only animated plugins should depend on this
+    pub frame: isize,
+    // The project format, which sizes generators and anything
+    // else not given a size of its own. Only format dependent
+    // plugins should depend on this.
+    pub format: Format,
+    pub formats: &'a Formats,
+}
 
 #[derive(Clone, Debug)]
 pub struct Desc {
@@ -22,6 +44,8 @@ pub struct Desc {
     inputs: Enumeration,
     controls: Vec<Value>,
     controls_map: Enumeration,
+    // Indices of the controls naming files
+    files: Vec<usize>,
 }
 
 impl Desc {
@@ -34,6 +58,12 @@ impl Desc {
                 .map(|desc| desc.kind.clone())
                 .collect::<Vec<_>>(),
             inputs: Enumeration::new(inputs.iter().copied()),
+            files: controls
+                .iter()
+                .enumerate()
+                .filter(|(_, desc)| desc.file)
+                .map(|(i, _)| i)
+                .collect(),
         }
     }
 
@@ -49,6 +79,14 @@ impl Desc {
         self.inputs.index(name)
     }
 
+    pub fn control_name(&self, index: usize) -> Option<&str> {
+        self.controls_map.name(index)
+    }
+
+    pub fn input_name(&self, index: usize) -> Option<&str> {
+        self.inputs.name(index)
+    }
+
     pub fn inputs_len(&self) -> usize {
         self.inputs.len()
     }
@@ -56,23 +94,93 @@ impl Desc {
     pub fn controls(&self) -> Vec<Value> {
         self.controls.clone()
     }
+
+    pub fn is_file(&self, index: usize) -> bool {
+        self.files.contains(&index)
+    }
 }
 
 pub struct Plugin {
     render: Render,
+    roi: Roi,
     desc: Desc,
+    // Whether the output changes from frame to frame
+    animated: bool,
+    // Whether the output depends on the color configuration
+    color_managed: bool,
+    // Whether the output depends on the project format
+    format_dependent: bool,
 }
 
 impl Plugin {
+    // Plugins need the same region of their inputs
+    // as they render unless told otherwise
     pub fn new(render: Render, desc: Desc) -> Self {
-        Self { render, desc }
+        Self {
+            render,
+            roi: same_region,
+            desc,
+            animated: false,
+            color_managed: false,
+            format_dependent: false,
+        }
+    }
+
+    pub fn with_roi(self, roi: Roi) -> Self {
+        Self { roi, ..self }
+    }
+
+    pub fn animated(self) -> Self {
+        Self {
+            animated: true,
+            ..self
+        }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.animated
+    }
+
+    pub fn color_managed(self) -> Self {
+        Self {
+            color_managed: true,
+            ..self
+        }
+    }
+
+    pub fn is_color_managed(&self) -> bool {
+        self.color_managed
+    }
+
+    pub fn format_dependent(self) -> Self {
+        Self {
+            format_dependent: true,
+            ..self
+        }
+    }
+
+    pub fn is_format_dependent(&self) -> bool {
+        self.format_dependent
     }
 
     pub fn desc(&self) -> &Desc {
         &self.desc
     }
 
-    pub fn render(&self, inputs: Inputs, controls: Controls) -> Result<Image, String> {
-        (self.render)(inputs, controls)
+    pub fn render(
+        &self,
+        inputs: Inputs,
+        controls: Controls,
+        ctx: &Context,
+    ) -> Result<Image, String> {
+        (self.render)(inputs, controls, ctx)
     }
+
+    pub fn roi(&self, input: usize, region: Option<Rect>, controls: Controls) -> Option<Rect> {
+        region.and_then(|region| (self.roi)(input, region, controls))
+    }
+}
+
+fn same_region(_: usize, region: Rect, _: Controls) -> Option<Rect> {
+    Some(region)
 }
@@ -1,7 +1,31 @@
 pub mod adjust;
 pub mod blur;
+pub mod channel_key;
+pub mod checkerboard;
+pub mod colorbars;
+pub mod colorspace;
+pub mod constant;
+pub mod convolve;
 pub mod crop;
+pub mod defocus;
+pub mod difference_key;
+pub mod dilate;
+pub mod edge_detect;
+pub mod erode;
+pub mod grade;
+pub mod gradient;
+pub mod keyer;
 pub mod load;
+pub mod luma_key;
+pub mod lut;
+pub mod matte_choke;
 pub mod merge;
+pub mod noise;
+pub mod premult;
+pub mod ramp;
+pub mod reformat;
 pub mod resize;
+pub mod roto;
+pub mod sharpen;
 pub mod shuffle;
+pub mod unpremult;
@@ -1,4 +1,8 @@
-use crate::{image::Image, plugin::*, utils::Value};
+use crate::{
+    image::Image,
+    plugin::*,
+    utils::{Rect, Value},
+};
 use rayon::prelude::*;
 
 enum Parameters {
@@ -9,9 +13,9 @@ enum Parameters {
     InvertGamma,
 }
 
-const KR: f32 = 0.2126;
-const KG: f32 = 0.7152;
-const KB: f32 = 0.0722;
+pub(crate) const KR: f32 = 0.2126;
+pub(crate) const KG: f32 = 0.7152;
+pub(crate) const KB: f32 = 0.0722;
 
 pub fn create() -> Plugin {
     let controls = [
@@ -25,22 +29,18 @@ pub fn create() -> Plugin {
     Plugin::new(render, desc)
 }
 
-fn render(inputs: Inputs, controls: Controls) -> Result<Image, String> {
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
     let bg = match inputs[0] {
         Some(bg) => Ok(bg),
         None => Err("Invalid background input".to_string()),
     }?;
 
-    let mut src_channels = bg.channels();
-    let src_r = src_channels.next().ok_or("No red channel")?;
-    let src_g = src_channels.next().ok_or("No green channel")?;
-    let src_b = src_channels.next().ok_or("No blue channel")?;
+    let [src_r, src_g, src_b] = bg.rgb().ok_or("No RGB channels")?;
 
-    let mut out = Image::from_desc(bg.desc());
-    let mut dst_channels = out.channels_mut();
-    let dst_r = dst_channels.next().unwrap();
-    let dst_g = dst_channels.next().unwrap();
-    let dst_b = dst_channels.next().unwrap();
+    // Channels other than RGB pass through
+    let mut out = bg.clone_region(ctx.region);
+    let out_window = out.data_window();
+    let [dst_r, dst_g, dst_b] = out.rgb_mut().unwrap();
 
     let hue_rotation = controls[Parameters::HueRotation as usize].as_real();
     let saturation = controls[Parameters::Saturation as usize].as_real();
@@ -57,13 +57,15 @@ fn render(inputs: Inputs, controls: Controls) -> Result<Image, String> {
     // Tried using the FromIterator approach here,
     // but in parallel, Rayon couldn't flatten the
     // inner iterator on chunks.
+    let src = bg.local_region(Some(out_window));
+    let dst = Some(Rect::from_size(out_window.size()));
     src_r
-        .par_lines()
-        .zip(src_g.par_lines())
-        .zip(src_b.par_lines())
-        .zip(dst_r.par_lines_mut())
-        .zip(dst_g.par_lines_mut())
-        .zip(dst_b.par_lines_mut())
+        .par_region_lines(src)
+        .zip(src_g.par_region_lines(src))
+        .zip(src_b.par_region_lines(src))
+        .zip(dst_r.par_region_lines_mut(dst))
+        .zip(dst_g.par_region_lines_mut(dst))
+        .zip(dst_b.par_region_lines_mut(dst))
         .for_each(|(((((src_r, src_g), src_b), dst_r), dst_g), dst_b)| {
             for (((((src_r, src_g), src_b), dst_r), dst_g), dst_b) in src_r
                 .iter()
@@ -87,8 +89,9 @@ fn render(inputs: Inputs, controls: Controls) -> Result<Image, String> {
                     y.powf(gamma)
                 };
 
-                u = u * cos - v * sin;
+                let rotated_u = u * cos - v * sin;
                 v = u * sin + v * cos;
+                u = rotated_u;
 
                 u *= saturation;
                 v *= saturation;
@@ -99,18 +102,11 @@ fn render(inputs: Inputs, controls: Controls) -> Result<Image, String> {
             }
         });
 
-    // We create dst_a twice, once zeroed and once cloned.
-    // Could be improved.
-    if let Some(src_a) = src_channels.next() {
-        let dst_a = dst_channels.next().unwrap();
-        *dst_a = src_a.clone();
-    }
-
     Ok(out)
 }
 
 fn roughly_one(x: f32) -> bool {
-    (x - 1.0).abs() < std::f32::EPSILON
+    (x - 1.0).abs() < f32::EPSILON
 }
 
 // Inaccurate for HDR
@@ -2,7 +2,7 @@ use crate::{
     control,
     image::{Channel, Image},
     plugin::{self, *},
-    utils::{Value, Vec2U},
+    utils::{Rect, Value, Vec2I, Vec2U},
 };
 use rayon::prelude::*;
 use std::cmp::{max, min};
@@ -14,47 +14,81 @@ enum Parameters {
 pub fn create() -> Plugin {
     let controls = [control::Desc::new("size", Value::Integer(0))];
     let desc = plugin::Desc::new("blur", &["bg"], &controls);
-    Plugin::new(render, desc)
+    Plugin::new(render, desc).with_roi(roi)
 }
 
-fn render(inputs: Inputs, controls: Controls) -> Result<Image, String> {
+fn roi(_: usize, region: Rect, controls: Controls) -> Option<Rect> {
+    let size = controls[Parameters::Size as usize].as_uint() as isize;
+    Some(region.grow(Vec2I::new(size, size)))
+}
+
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
     let bg = match inputs[0] {
         Some(bg) => Ok(bg),
         None => Err(String::from("Invalid background input")),
     }?;
 
-    let filter = {
-        let size = controls[Parameters::Size as usize].as_uint();
-        let f_size = size as f32;
-        (0..1 + size * 2)
-            .map(|i| {
-                let x = i as f32 - f_size;
-                let x = 1.0 - x.abs() / f_size;
-                let rcp = 1.0 - x;
-                let x = rcp * x * x + x * (1.0 - rcp * rcp);
-                x / f_size
-            })
-            .collect::<Vec<_>>()
-    };
+    let size = controls[Parameters::Size as usize].as_uint();
+    let filter = filter(size);
 
-    Ok(bg
-        .par_channels()
-        .map(|channel| {
-            let tmp = blur_axis(&channel, &filter);
-            blur_axis(&tmp, &filter)
+    // The image is black past its data window,
+    // so the blur spreads out into that space
+    let grown = bg
+        .data_window()
+        .grow(Vec2I::new(size as isize, size as isize));
+    Ok(bg.reframed(grown).map_premultiplied(|bg| {
+        let region = bg.local_region(ctx.region);
+        bg.par_channels()
+            .map(|channel| blur_channel(channel, &filter, channel.clip(region)))
+            .collect::<Image>()
+    }))
+}
+
+// Weights for a blur reaching size pixels to either side
+pub(crate) fn filter(size: usize) -> Vec<f32> {
+    if size == 0 {
+        return vec![1.0];
+    }
+    let f_size = size as f32;
+    (0..1 + size * 2)
+        .map(|i| {
+            let x = i as f32 - f_size;
+            let x = 1.0 - x.abs() / f_size;
+            let rcp = 1.0 - x;
+            let x = rcp * x * x + x * (1.0 - rcp * rcp);
+            x / f_size
         })
-        .collect::<Image>())
+        .collect()
+}
+
+// Blurs the region of a channel along both axes
+pub(crate) fn blur_channel(channel: &Channel, filter: &[f32], region: Rect) -> Channel {
+    // The first pass covers enough rows
+    // to feed the second one
+    let reach = Vec2I::new(0, filter.len() as isize / 2);
+    let tmp = blur_axis(channel, filter, region.grow(reach));
+    blur_axis(&tmp, filter, transposed(region))
+}
+
+fn transposed(rect: Rect) -> Rect {
+    Rect::new(
+        Vec2I::new(rect.min.y, rect.min.x),
+        Vec2I::new(rect.max.y, rect.max.x),
+    )
 }
 
-fn blur_axis(channel: &Channel, filter: &[f32]) -> Channel {
+// Filters rows within the region and returns the transpose
+fn blur_axis(channel: &Channel, filter: &[f32], region: Rect) -> Channel {
+    let region = channel.clip(Some(region));
     let max_dim = channel.size().x as isize - 1;
     let flipped = Vec2U::new(channel.size().y, channel.size().x);
     let mut out = Channel::black(flipped);
-    let size = (filter.len() / 2 - 1) as isize;
+    // The middle weight falls on the pixel itself
+    let size = (filter.len() / 2) as isize;
     // Can't paralellize over lines because it requires
     // multiple mutable references to `out`
-    for y in 0..channel.size().y {
-        for x in 0..channel.size().x {
+    for y in region.rows() {
+        for x in region.columns() {
             let out_index = x * channel.size().y + y;
             out[out_index] = filter.iter().enumerate().fold(0.0, |acc, (i, cell)| {
                 let sample_x = x as isize + i as isize - size;
@@ -0,0 +1,81 @@
+use crate::{
+    image::{self, Channel, Image},
+    plugin::{self, *},
+    utils::Value,
+};
+use rayon::prelude::*;
+
+enum Parameters {
+    Channel,
+    Low,
+    High,
+    LowSoftness,
+    HighSoftness,
+    Invert,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("channel", Value::Text(image::RED.into())),
+        control::Desc::new("low", Value::Real(0.5)),
+        control::Desc::new("high", Value::Real(1.0)),
+        control::Desc::new("low_softness", Value::Real(0.0)),
+        control::Desc::new("high_softness", Value::Real(0.0)),
+        control::Desc::new("invert", Value::Boolean(false)),
+    ];
+    let desc = plugin::Desc::new("channel_key", &["bg"], &controls);
+    Plugin::new(render, desc)
+}
+
+// The matte is opaque where the channel lies between low and
+// high and fades out over the softness beyond either of them.
+// Other channels pass through, unpremultiplied.
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => Ok(bg),
+        None => Err("Invalid background input".to_string()),
+    }?;
+
+    let name = controls[Parameters::Channel as usize].as_str();
+    let low = controls[Parameters::Low as usize].as_real();
+    let high = controls[Parameters::High as usize].as_real();
+    let low_softness = controls[Parameters::LowSoftness as usize].as_real();
+    let high_softness = controls[Parameters::HighSoftness as usize].as_real();
+    let invert = controls[Parameters::Invert as usize].as_bool();
+
+    let mut out = bg.clone_region(ctx.region);
+    out.unpremultiply();
+    let channel = out
+        .channel_by_name(name)
+        .ok_or(format!("Channel not found: {}", name))?;
+    let mut matte = Channel::black(out.size());
+    matte
+        .par_lines_mut()
+        .zip(channel.par_lines())
+        .for_each(|(matte, channel)| {
+            for (matte, value) in matte.iter_mut().zip(channel.iter()) {
+                let key = soft_range(*value, low, high, low_softness, high_softness);
+                *matte = if invert { 1.0 - key } else { key };
+            }
+        });
+    out.insert_channel(image::ALPHA.to_string(), matte);
+    out.set_premultiplied(false);
+    Ok(out)
+}
+
+// One between low and high, falling linearly to
+// zero over the softness past either end
+fn soft_range(value: f32, low: f32, high: f32, low_soft: f32, high_soft: f32) -> f32 {
+    let edge = |distance: f32, softness: f32| {
+        if softness > 0.0 {
+            1.0 - distance / softness
+        } else if distance > 0.0 {
+            0.0
+        } else {
+            1.0
+        }
+    };
+    edge(low - value, low_soft)
+        .min(edge(value - high, high_soft))
+        .clamp(0.0, 1.0)
+}
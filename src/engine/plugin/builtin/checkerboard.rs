@@ -0,0 +1,74 @@
+use super::constant::{frame_format, generate};
+use crate::{
+    control,
+    image::Image,
+    plugin::{self, *},
+    utils::Value,
+};
+
+enum Parameters {
+    Width,
+    Height,
+    Size,
+    FirstR,
+    FirstG,
+    FirstB,
+    FirstA,
+    SecondR,
+    SecondG,
+    SecondB,
+    SecondA,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("width", Value::Integer(0)),
+        control::Desc::new("height", Value::Integer(0)),
+        control::Desc::new("size", Value::Integer(64)),
+        control::Desc::new("first_r", Value::Real(0.1)),
+        control::Desc::new("first_g", Value::Real(0.1)),
+        control::Desc::new("first_b", Value::Real(0.1)),
+        control::Desc::new("first_a", Value::Real(1.0)),
+        control::Desc::new("second_r", Value::Real(0.5)),
+        control::Desc::new("second_g", Value::Real(0.5)),
+        control::Desc::new("second_b", Value::Real(0.5)),
+        control::Desc::new("second_a", Value::Real(1.0)),
+    ];
+    let desc = plugin::Desc::new("checkerboard", &[], &controls);
+    Plugin::new(render, desc).format_dependent()
+}
+
+// Squares of the size in pixels, starting
+// with the first color in the top left
+fn render(_: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let format = frame_format(
+        controls,
+        Parameters::Width as usize,
+        Parameters::Height as usize,
+        ctx,
+    );
+    let square = controls[Parameters::Size as usize].as_uint().max(1) as f32;
+    let first = [
+        Parameters::FirstR,
+        Parameters::FirstG,
+        Parameters::FirstB,
+        Parameters::FirstA,
+    ]
+    .map(|channel| controls[channel as usize].as_real());
+    let second = [
+        Parameters::SecondR,
+        Parameters::SecondG,
+        Parameters::SecondB,
+        Parameters::SecondA,
+    ]
+    .map(|channel| controls[channel as usize].as_real());
+    Ok(generate(format, ctx.region, |pos| {
+        let x = (pos.x / square) as usize;
+        let y = (pos.y / square) as usize;
+        if (x + y).is_multiple_of(2) {
+            first
+        } else {
+            second
+        }
+    }))
+}
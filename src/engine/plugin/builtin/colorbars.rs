@@ -0,0 +1,52 @@
+use super::constant::{frame_format, generate};
+use crate::{
+    control,
+    image::Image,
+    plugin::{self, *},
+    utils::Value,
+};
+
+enum Parameters {
+    Width,
+    Height,
+    Intensity,
+}
+
+// White, yellow, cyan, green, magenta, red and blue
+const BARS: [[f32; 3]; 7] = [
+    [1.0, 1.0, 1.0],
+    [1.0, 1.0, 0.0],
+    [0.0, 1.0, 1.0],
+    [0.0, 1.0, 0.0],
+    [1.0, 0.0, 1.0],
+    [1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0],
+];
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("width", Value::Integer(0)),
+        control::Desc::new("height", Value::Integer(0)),
+        control::Desc::new("intensity", Value::Real(0.75)),
+    ];
+    let desc = plugin::Desc::new("colorbars", &[], &controls);
+    Plugin::new(render, desc).format_dependent()
+}
+
+// Vertical bars of equal width across the frame, with
+// colors scaled by the intensity like 75% bars
+fn render(_: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let format = frame_format(
+        controls,
+        Parameters::Width as usize,
+        Parameters::Height as usize,
+        ctx,
+    );
+    let intensity = controls[Parameters::Intensity as usize].as_real();
+    let width = format.width.max(1) as f32;
+    Ok(generate(format, ctx.region, |pos| {
+        let bar = ((pos.x / width * BARS.len() as f32) as usize).min(BARS.len() - 1);
+        let [r, g, b] = BARS[bar];
+        [r * intensity, g * intensity, b * intensity, 1.0]
+    }))
+}
@@ -0,0 +1,36 @@
+use crate::{
+    color::SCENE_LINEAR,
+    control,
+    image::Image,
+    plugin::{self, *},
+    utils::Value,
+};
+
+enum Parameters {
+    In,
+    Out,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("in", Value::Text(SCENE_LINEAR.into())),
+        control::Desc::new("out", Value::Text(SCENE_LINEAR.into())),
+    ];
+    let desc = plugin::Desc::new("colorspace", &["bg"], &controls);
+    Plugin::new(render, desc).color_managed()
+}
+
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => bg,
+        None => return Err(String::from("Invalid background input")),
+    };
+
+    let from = controls[Parameters::In as usize].as_str();
+    let to = controls[Parameters::Out as usize].as_str();
+    let processor = ctx.colors.processor(from, to)?;
+
+    let mut out = bg.clone_region(ctx.region);
+    processor.apply_region(&mut out, ctx.region);
+    Ok(out)
+}
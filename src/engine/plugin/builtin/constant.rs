@@ -0,0 +1,101 @@
+use crate::{
+    control,
+    format::Format,
+    image::{self, Image},
+    plugin::{self, *},
+    utils::{Rect, Value, Vec2F},
+};
+use rayon::prelude::*;
+
+enum Parameters {
+    Width,
+    Height,
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("width", Value::Integer(0)),
+        control::Desc::new("height", Value::Integer(0)),
+        control::Desc::new("red", Value::Real(0.0)),
+        control::Desc::new("green", Value::Real(0.0)),
+        control::Desc::new("blue", Value::Real(0.0)),
+        control::Desc::new("alpha", Value::Real(1.0)),
+    ];
+    let desc = plugin::Desc::new("constant", &[], &controls);
+    Plugin::new(render, desc).format_dependent()
+}
+
+fn render(_: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let format = frame_format(
+        controls,
+        Parameters::Width as usize,
+        Parameters::Height as usize,
+        ctx,
+    );
+    let color = [
+        Parameters::Red,
+        Parameters::Green,
+        Parameters::Blue,
+        Parameters::Alpha,
+    ]
+    .map(|channel| controls[channel as usize].as_real());
+    Ok(generate(format, ctx.region, |_| color))
+}
+
+// Reads the width and height controls of a generator. A zero
+// for either follows the project format, pixel aspect and all.
+pub(crate) fn frame_format(
+    controls: Controls,
+    width: usize,
+    height: usize,
+    ctx: &Context,
+) -> Format {
+    let width = controls[width].as_uint();
+    let height = controls[height].as_uint();
+    if width == 0 || height == 0 {
+        ctx.format
+    } else {
+        Format::new(width, height, 1.0)
+    }
+}
+
+// Builds an RGBA image in the given format from unpremultiplied
+// colors at the center of each pixel. Only pixels in the region
+// are filled.
+pub(crate) fn generate<F>(format: Format, region: Option<Rect>, pixel: F) -> Image
+where
+    F: Fn(Vec2F) -> [f32; 4] + Sync,
+{
+    let frame = Rect::from_size(format.size());
+    let data = region.map_or(frame, |region| region.intersect(frame));
+    let mut image = Image::from_desc(image::Desc::new(data.size(), 4));
+    // Each pixel is worked out once and written to every channel
+    if let [red, green, blue, alpha] = image.channels_mut().collect::<Vec<_>>().as_mut_slice() {
+        red.par_lines_mut()
+            .zip(green.par_lines_mut())
+            .zip(blue.par_lines_mut())
+            .zip(alpha.par_lines_mut())
+            .enumerate()
+            .for_each(|(y, (((red, green), blue), alpha))| {
+                for x in 0..red.len() {
+                    let pos = Vec2F::new(
+                        (data.min.x + x as isize) as f32 + 0.5,
+                        (data.min.y + y as isize) as f32 + 0.5,
+                    );
+                    let [r, g, b, a] = pixel(pos);
+                    red[x] = r;
+                    green[x] = g;
+                    blue[x] = b;
+                    alpha[x] = a;
+                }
+            });
+    }
+    image.set_data_origin(data.min);
+    image.set_display_window(frame);
+    image.set_pixel_aspect(format.pixel_aspect);
+    image
+}
@@ -0,0 +1,186 @@
+use crate::{
+    control,
+    image::{Channel, ChannelBuilder, Image},
+    plugin::{self, *},
+    utils::{
+        fft::{self, Complex},
+        Rect, Value, Vec2I, Vec2U,
+    },
+};
+use rayon::prelude::*;
+
+enum Parameters {
+    Normalize,
+}
+
+// Rough number of multiply-adds per element an FFT
+// costs relative to the direct approach
+const FFT_COST: f32 = 6.0;
+
+pub fn create() -> Plugin {
+    let controls = [control::Desc::new("normalize", Value::Boolean(true))];
+    let desc = plugin::Desc::new("convolve", &["bg", "kernel"], &controls);
+    Plugin::new(render, desc).with_roi(roi)
+}
+
+// How far the background is sampled depends on
+// a kernel size that isn't known until it renders
+fn roi(_: usize, _: Rect, _: Controls) -> Option<Rect> {
+    None
+}
+
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => bg,
+        None => return Err(String::from("Invalid background input")),
+    };
+
+    let kernel = match inputs[1] {
+        Some(kernel) => kernel,
+        // Pass through background input
+        None => return Ok(bg.clone_region(ctx.region)),
+    };
+
+    let normalize = controls[Parameters::Normalize as usize].as_bool();
+    let first = kernel.channels().next().ok_or("Kernel has no channels")?;
+
+    let grown = spread(bg.data_window(), first);
+    Ok(bg.reframed(grown).map_premultiplied(|bg| {
+        let region = bg.local_region(ctx.region);
+        bg.par_channels()
+            .enumerate()
+            .map(|(i, channel)| {
+                // Reuse the first kernel channel when there
+                // aren't enough to go around
+                let kernel = kernel.channels().nth(i).unwrap_or(first);
+                if normalize {
+                    convolve(channel, &normalized(kernel), region)
+                } else {
+                    convolve(channel, kernel, region)
+                }
+            })
+            .collect::<Image>()
+    }))
+}
+
+// The data window a convolution leaves behind. The image
+// is black outside of it, so that's as far as it spreads.
+pub(crate) fn spread(data: Rect, kernel: &Channel) -> Rect {
+    let center: Vec2I = kernel_center(kernel).into();
+    let size: Vec2I = kernel.size().into();
+    Rect::new(
+        data.min - center,
+        data.max + size - center - Vec2I::new(1, 1),
+    )
+}
+
+pub(crate) fn normalized(kernel: &Channel) -> Channel {
+    let sum: f32 = kernel.elements().sum();
+    if sum.abs() < f32::EPSILON {
+        return kernel.clone();
+    }
+    let mut out = kernel.clone();
+    for element in out.elements_mut() {
+        *element /= sum;
+    }
+    out
+}
+
+// The kernel is centered on its middle pixel and samples past
+// the edges of the channel are clamped. Only the region is
+// guaranteed to be filled in.
+pub(crate) fn convolve(channel: &Channel, kernel: &Channel, region: Option<Rect>) -> Channel {
+    let region = channel.clip(region);
+    let padded = padded_size(channel.size(), kernel.size());
+    let direct_cost = kernel.size().area() as f32 * region.size().area() as f32;
+    let fft_cost = FFT_COST * (padded.area() as f32).log2() * padded.area() as f32;
+    if direct_cost > fft_cost {
+        convolve_fft(channel, kernel)
+    } else {
+        convolve_direct(channel, kernel, region)
+    }
+}
+
+fn convolve_direct(channel: &Channel, kernel: &Channel, region: Rect) -> Channel {
+    let size = channel.size();
+    let center = kernel_center(kernel);
+    let max_x = size.x as isize - 1;
+    let max_y = size.y as isize - 1;
+    let mut out = Channel::black(size);
+    let rows = region.rows();
+    out.par_lines_mut()
+        .enumerate()
+        .skip(rows.start)
+        .take(rows.len())
+        .for_each(|(y, line)| {
+            for x in region.columns() {
+                let mut acc = 0.0;
+                for (j, k_line) in kernel.lines().enumerate() {
+                    let src_y = (y as isize + center.y as isize - j as isize).clamp(0, max_y);
+                    let src_y = src_y as usize * size.x;
+                    for (i, k) in k_line.iter().enumerate() {
+                        let src_x = (x as isize + center.x as isize - i as isize).clamp(0, max_x);
+                        acc += k * channel[src_y + src_x as usize];
+                    }
+                }
+                line[x] = acc;
+            }
+        });
+    out
+}
+
+fn convolve_fft(channel: &Channel, kernel: &Channel) -> Channel {
+    let size = channel.size();
+    let k_size = kernel.size();
+    let padded = padded_size(size, k_size);
+
+    // Extend the edges of the source so that they get
+    // clamped the same way as in the direct approach
+    let pad_lo = k_size - kernel_center(kernel) - Vec2U::new(1, 1);
+    let extended = size + k_size - Vec2U::new(1, 1);
+    let mut src = vec![Complex::default(); padded.area()];
+    src.par_chunks_mut(padded.x)
+        .take(extended.y)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let src_y = (y as isize - pad_lo.y as isize).clamp(0, size.y as isize - 1);
+            let src_y = src_y as usize * size.x;
+            for (x, element) in row.iter_mut().take(extended.x).enumerate() {
+                let src_x = (x as isize - pad_lo.x as isize).clamp(0, size.x as isize - 1);
+                *element = Complex::new(channel[src_y + src_x as usize], 0.0);
+            }
+        });
+
+    let mut spectrum = vec![Complex::default(); padded.area()];
+    for (row, k_line) in spectrum.chunks_exact_mut(padded.x).zip(kernel.lines()) {
+        for (element, k) in row.iter_mut().zip(k_line.iter()) {
+            *element = Complex::new(*k, 0.0);
+        }
+    }
+
+    fft::fft2d(&mut src, padded, false);
+    fft::fft2d(&mut spectrum, padded, false);
+    src.par_iter_mut()
+        .zip(spectrum.par_iter())
+        .for_each(|(s, k)| *s = *s * *k);
+    fft::fft2d(&mut src, padded, true);
+
+    let offset = k_size - Vec2U::new(1, 1);
+    let src = &src;
+    (0..size.y)
+        .flat_map(|y| (0..size.x).map(move |x| src[(y + offset.y) * padded.x + x + offset.x].re))
+        .collect::<ChannelBuilder>()
+        .build(size)
+}
+
+fn kernel_center(kernel: &Channel) -> Vec2U {
+    Vec2U::new(kernel.size().x / 2, kernel.size().y / 2)
+}
+
+fn padded_size(size: Vec2U, kernel: Vec2U) -> Vec2U {
+    let extended = size + kernel - Vec2U::new(1, 1);
+    Vec2U::new(
+        extended.x.next_power_of_two(),
+        extended.y.next_power_of_two(),
+    )
+}
@@ -1,11 +1,9 @@
-use crate::image::ChannelBuilder;
 use crate::{
     control,
     image::Image,
     plugin::{self, *},
-    utils::{Value, Vec2U},
+    utils::{Rect, Value, Vec2I},
 };
-use rayon::prelude::*;
 use std::cmp::max;
 
 enum Parameters {
@@ -13,6 +11,7 @@ enum Parameters {
     Top,
     Right,
     Bottom,
+    Clip,
 }
 
 pub fn create() -> Plugin {
@@ -21,12 +20,24 @@ pub fn create() -> Plugin {
         control::Desc::new("top", Value::Integer(0)),
         control::Desc::new("right", Value::Integer(0)),
         control::Desc::new("bottom", Value::Integer(0)),
+        control::Desc::new("clip", Value::Boolean(true)),
     ];
     let desc = plugin::Desc::new("crop", &["bg"], &controls);
-    Plugin::new(render, desc)
+    Plugin::new(render, desc).with_roi(roi)
 }
 
-fn render(inputs: Inputs, controls: Controls) -> Result<Image, String> {
+// Output pixels come from the input shifted by the left and top margins
+fn roi(_: usize, region: Rect, controls: Controls) -> Option<Rect> {
+    let left = controls[Parameters::Left as usize].as_int();
+    let top = controls[Parameters::Top as usize].as_int();
+    Some(region.translate(Vec2I::new(-left, -top)))
+}
+
+// Margins grow the frame when positive and shrink it when
+// negative, with the image moving along with the top left
+// corner. Unless clipped, pixels outside the new frame are
+// kept in the data window.
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
     let bg = match inputs[0] {
         Some(bg) => bg,
         None => return Err(String::from("Invalid background input")),
@@ -36,37 +47,31 @@ fn render(inputs: Inputs, controls: Controls) -> Result<Image, String> {
     let top = controls[Parameters::Top as usize].as_int();
     let right = controls[Parameters::Right as usize].as_int();
     let bottom = controls[Parameters::Bottom as usize].as_int();
+    let clip = controls[Parameters::Clip as usize].as_bool();
 
-    let bottom = bg.desc().size.y as isize + bottom;
-    let right = bg.desc().size.x as isize + right;
+    let frame = bg.display_window();
+    let size: Vec2I = frame.size().into();
+    let display = Rect::new(
+        frame.min,
+        frame.min + Vec2I::new(max(0, size.x + left + right), max(0, size.y + top + bottom)),
+    );
 
-    let width = max(0, right + left) as usize;
-    let height = max(0, bottom + top) as usize;
-
-    let src_sz_x = bg.desc().size.x as isize;
-    let src_sz_y = bg.desc().size.y as isize;
-
-    Ok(bg
-        .par_channels()
-        .map(|src_channel| {
-            (0..height)
-                .map(|y| {
-                    (0..width).map(move |x| {
-                        let src_x = x as isize - left;
-                        let src_y = y as isize - top;
-                        let src_idx = src_y * src_sz_x + src_x;
-                        let black =
-                            src_x < 0 || src_x > src_sz_x - 1 || src_y < 0 || src_y > src_sz_y - 1;
-                        if black {
-                            0.0
-                        } else {
-                            src_channel[src_idx as usize]
-                        }
-                    })
-                })
-                .flatten()
-                .collect::<ChannelBuilder>()
-                .build(Vec2U::new(width, height))
-        })
-        .collect::<Image>())
+    let offset = Vec2I::new(left, top);
+    let moved = bg.data_window().translate(offset);
+    let mut kept = if clip {
+        moved.intersect(display)
+    } else {
+        moved
+    };
+    if let Some(region) = ctx.region {
+        kept = kept.intersect(region);
+    }
+    let mut out = if kept == moved {
+        bg.clone()
+    } else {
+        bg.reframed(kept.translate(-offset))
+    };
+    out.set_data_origin(kept.min);
+    out.set_display_window(display);
+    Ok(out)
 }
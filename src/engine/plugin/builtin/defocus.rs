@@ -0,0 +1,99 @@
+use super::convolve::{convolve, normalized, spread};
+use crate::{
+    control,
+    image::{Channel, ChannelBuilder, Image},
+    plugin::{self, *},
+    utils::{Rect, Value, Vec2F, Vec2I, Vec2U},
+};
+use rayon::prelude::*;
+use std::f32::consts::PI;
+
+enum Parameters {
+    Size,
+    Blades,
+    Rotation,
+}
+
+// Subsamples per axis when rasterizing the aperture
+const SUBSAMPLES: usize = 4;
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("size", Value::Real(0.0)),
+        control::Desc::new("blades", Value::Integer(0)),
+        control::Desc::new("rotation", Value::Real(0.0)),
+    ];
+    let desc = plugin::Desc::new("defocus", &["bg"], &controls);
+    Plugin::new(render, desc).with_roi(roi)
+}
+
+fn roi(_: usize, region: Rect, controls: Controls) -> Option<Rect> {
+    let extent = controls[Parameters::Size as usize]
+        .as_real()
+        .max(0.0)
+        .ceil() as isize;
+    Some(region.grow(Vec2I::new(extent, extent)))
+}
+
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => bg,
+        None => return Err(String::from("Invalid background input")),
+    };
+
+    let radius = controls[Parameters::Size as usize].as_real();
+    let blades = controls[Parameters::Blades as usize].as_uint();
+    let rotation = controls[Parameters::Rotation as usize].as_real();
+
+    if radius <= 0.0 {
+        return Ok(bg.clone_region(ctx.region));
+    }
+
+    let kernel = normalized(&aperture(radius, blades, rotation));
+    let grown = spread(bg.data_window(), &kernel);
+    Ok(bg.reframed(grown).map_premultiplied(|bg| {
+        let region = bg.local_region(ctx.region);
+        bg.par_channels()
+            .map(|channel| convolve(channel, &kernel, region))
+            .collect::<Image>()
+    }))
+}
+
+// Fewer than three blades gives a disc
+fn aperture(radius: f32, blades: usize, rotation: f32) -> Channel {
+    let extent = radius.ceil() as usize;
+    let dim = extent * 2 + 1;
+    let step = (SUBSAMPLES as f32).recip();
+    (0..dim * dim)
+        .map(|i| {
+            let corner = Vec2F::new(
+                (i % dim) as f32 - extent as f32 - 0.5,
+                (i / dim) as f32 - extent as f32 - 0.5,
+            );
+            let covered = (0..SUBSAMPLES * SUBSAMPLES)
+                .filter(|sample| {
+                    let offset = Vec2F::new(
+                        ((sample % SUBSAMPLES) as f32 + 0.5) * step,
+                        ((sample / SUBSAMPLES) as f32 + 0.5) * step,
+                    );
+                    is_inside(corner + offset, radius, blades, rotation)
+                })
+                .count();
+            covered as f32 * step * step
+        })
+        .collect::<ChannelBuilder>()
+        .build(Vec2U::new(dim, dim))
+}
+
+fn is_inside(pos: Vec2F, radius: f32, blades: usize, rotation: f32) -> bool {
+    if blades < 3 {
+        return pos.length() <= radius;
+    }
+    // Test against the inscribed radius along each edge normal
+    let wedge = 2.0 * PI / blades as f32;
+    let apothem = radius * (wedge / 2.0).cos();
+    (0..blades).all(|i| {
+        let angle = rotation + (i as f32 + 0.5) * wedge;
+        pos.x * angle.cos() + pos.y * angle.sin() <= apothem
+    })
+}
@@ -0,0 +1,79 @@
+use crate::{
+    image::{self, Channel, Image},
+    plugin::{self, *},
+    utils::Value,
+};
+use rayon::prelude::*;
+use std::borrow::Cow;
+
+enum Parameters {
+    Threshold,
+    Softness,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("threshold", Value::Real(0.05)),
+        control::Desc::new("softness", Value::Real(0.1)),
+    ];
+    let desc = plugin::Desc::new("difference_key", &["bg", "fg"], &controls);
+    Plugin::new(render, desc)
+}
+
+// Keys the foreground against a clean background plate. The
+// matte is zero where no color channel differs by more than the
+// threshold and reaches one the softness beyond it. The output is
+// the foreground with the matte as alpha, unpremultiplied.
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => Ok(bg),
+        None => Err("Invalid background input".to_string()),
+    }?;
+    let fg = match inputs[1] {
+        Some(fg) => Ok(fg),
+        None => Err("Invalid foreground input".to_string()),
+    }?;
+
+    let threshold = controls[Parameters::Threshold as usize].as_real();
+    let softness = controls[Parameters::Softness as usize].as_real();
+
+    let mut out = fg.clone_region(ctx.region);
+    out.unpremultiply();
+
+    // Background pixels line up with the foreground data window
+    let mut bg = if bg.data_window() == out.data_window() {
+        Cow::Borrowed(bg)
+    } else {
+        Cow::Owned(bg.reframed(out.data_window()))
+    };
+    if bg.is_premultiplied() {
+        bg.to_mut().unpremultiply();
+    }
+
+    let [fg_r, fg_g, fg_b] = out.rgb().ok_or("No RGB channels in foreground")?;
+    let [bg_r, bg_g, bg_b] = bg.rgb().ok_or("No RGB channels in background")?;
+    let mut matte = Channel::black(out.size());
+    matte
+        .par_lines_mut()
+        .zip(fg_r.par_lines().zip(bg_r.par_lines()))
+        .zip(fg_g.par_lines().zip(bg_g.par_lines()))
+        .zip(fg_b.par_lines().zip(bg_b.par_lines()))
+        .for_each(|(((matte, r), g), b)| {
+            for (x, matte) in matte.iter_mut().enumerate() {
+                let difference = [r, g, b]
+                    .iter()
+                    .map(|(fg, bg)| (fg[x] - bg[x]).abs())
+                    .fold(0.0, f32::max);
+                *matte = if softness > 0.0 {
+                    ((difference - threshold) / softness).clamp(0.0, 1.0)
+                } else if difference > threshold {
+                    1.0
+                } else {
+                    0.0
+                };
+            }
+        });
+    out.insert_channel(image::ALPHA.to_string(), matte);
+    out.set_premultiplied(false);
+    Ok(out)
+}
@@ -0,0 +1,26 @@
+use super::erode::{morph, roi};
+use crate::{
+    control,
+    image::{self, Image},
+    plugin::{self, *},
+    utils::Value,
+};
+
+// Shares its controls with erode
+enum Parameters {
+    Size,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("size", Value::Real(1.0)),
+        control::Desc::new("channel", Value::Text(image::ALPHA.into())),
+    ];
+    let desc = plugin::Desc::new("dilate", &["bg"], &controls);
+    Plugin::new(render, desc).with_roi(roi)
+}
+
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let size = controls[Parameters::Size as usize].as_real();
+    morph(inputs, controls, ctx, size)
+}
@@ -0,0 +1,87 @@
+use super::erode::selected;
+use crate::{
+    control,
+    image::{Channel, Image},
+    plugin::{self, *},
+    utils::{Rect, Value, Vec2I},
+};
+use rayon::prelude::*;
+
+enum Parameters {
+    Method,
+    Channel,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("method", Value::Text("sobel".into())),
+        control::Desc::new("channel", Value::Text("all".into())),
+    ];
+    let desc = plugin::Desc::new("edge_detect", &["bg"], &controls);
+    Plugin::new(render, desc).with_roi(roi)
+}
+
+fn roi(_: usize, region: Rect, _: Controls) -> Option<Rect> {
+    Some(region.grow(Vec2I::new(1, 1)))
+}
+
+// Sobel gives the length of the gradient and Laplacian the size
+// of the second derivative, both over each pixel and its eight
+// neighbors. Edges of the data window repeat outward.
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => Ok(bg),
+        None => Err(String::from("Invalid background input")),
+    }?;
+    let sobel = match controls[Parameters::Method as usize].as_str() {
+        "sobel" => true,
+        "laplacian" => false,
+        method => return Err(format!("Unrecognized edge method: {}", method)),
+    };
+    let channels = selected(bg, controls[Parameters::Channel as usize].as_str())?;
+
+    let mut out = bg.clone_region(ctx.region);
+    let offset = out.data_window().min - bg.data_window().min;
+    for i in channels {
+        let src = &bg[i];
+        let size = src.size();
+        let sample = |x: isize, y: isize| {
+            let x = x.clamp(0, size.x as isize - 1) as usize;
+            let y = y.clamp(0, size.y as isize - 1) as usize;
+            src[y * size.x + x]
+        };
+        out[i] = {
+            let mut edges = Channel::black(out.size());
+            edges.par_lines_mut().enumerate().for_each(|(y, line)| {
+                let y = y as isize + offset.y;
+                for (x, element) in line.iter_mut().enumerate() {
+                    let x = x as isize + offset.x;
+                    let n = |dx: isize, dy: isize| sample(x + dx, y + dy);
+                    *element = if sobel {
+                        let gx = n(1, -1) + 2.0 * n(1, 0) + n(1, 1)
+                            - n(-1, -1)
+                            - 2.0 * n(-1, 0)
+                            - n(-1, 1);
+                        let gy = n(-1, 1) + 2.0 * n(0, 1) + n(1, 1)
+                            - n(-1, -1)
+                            - 2.0 * n(0, -1)
+                            - n(1, -1);
+                        (gx * gx + gy * gy).sqrt()
+                    } else {
+                        let around = n(-1, -1)
+                            + n(0, -1)
+                            + n(1, -1)
+                            + n(-1, 0)
+                            + n(1, 0)
+                            + n(-1, 1)
+                            + n(0, 1)
+                            + n(1, 1);
+                        (around - 8.0 * n(0, 0)).abs()
+                    };
+                }
+            });
+            edges
+        };
+    }
+    Ok(out)
+}
@@ -0,0 +1,136 @@
+use crate::{
+    control,
+    image::{self, Image},
+    plugin::{self, *},
+    utils::{morphology, Rect, Value, Vec2I},
+};
+use rayon::prelude::*;
+
+enum Parameters {
+    Size,
+    Channel,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("size", Value::Real(1.0)),
+        control::Desc::new("channel", Value::Text(image::ALPHA.into())),
+    ];
+    let desc = plugin::Desc::new("erode", &["bg"], &controls);
+    Plugin::new(render, desc).with_roi(roi)
+}
+
+pub(crate) fn roi(_: usize, region: Rect, controls: Controls) -> Option<Rect> {
+    let reach = controls[Parameters::Size as usize].as_real().abs().ceil() as isize;
+    Some(region.grow(Vec2I::new(reach, reach)))
+}
+
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let size = controls[Parameters::Size as usize].as_real();
+    morph(inputs, controls, ctx, -size)
+}
+
+// Takes the maximum of the chosen channels over a disk reaching
+// the amount in pixels, or the minimum when it is negative. Values
+// are kept as they are, so soft edges stay soft as they move. A
+// fractional amount blends between the disks to either side. The
+// image is black past its data window, so it can grow into that
+// space.
+pub(crate) fn morph(
+    inputs: Inputs,
+    controls: Controls,
+    ctx: &Context,
+    amount: f32,
+) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => Ok(bg),
+        None => Err(String::from("Invalid background input")),
+    }?;
+    let channels = selected(bg, controls[Parameters::Channel as usize].as_str())?;
+
+    let radius = amount.abs();
+    let (inner, outer) = (radius.floor() as usize, radius.ceil() as usize);
+    let blend = radius.fract();
+    let reach = outer as isize;
+    let mut out = bg.reframed(bg.data_window().grow(Vec2I::new(reach, reach)));
+    let op = if amount < 0.0 { f32::min } else { f32::max };
+    for i in channels {
+        let size = out[i].size();
+        let values = out[i].elements().copied().collect::<Vec<_>>();
+        let lo = morphology::disk(&values, size, inner, op);
+        let hi = if outer == inner {
+            lo.clone()
+        } else {
+            morphology::disk(&values, size, outer, op)
+        };
+        out[i]
+            .par_elements_mut()
+            .zip(lo.par_iter().zip(hi.par_iter()))
+            .for_each(|(element, (lo, hi))| {
+                *element = lo + (hi - lo) * blend;
+            });
+    }
+    Ok(out.clone_region(ctx.region))
+}
+
+// Indices of the named channel, or of every channel for "all"
+pub(crate) fn selected(image: &Image, channel: &str) -> Result<Vec<usize>, String> {
+    match channel {
+        "all" => Ok((0..image.channel_count()).collect()),
+        name => image
+            .index_of_channel(name)
+            .map(|i| vec![i])
+            .ok_or(format!("Channel not found: {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::Engine, image::ChannelBuilder, utils::Vec2U};
+
+    // A cone falling off softly from the middle
+    fn matte() -> Image {
+        let size = Vec2U::new(24, 20);
+        let channel = (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| (x as f32 - 11.5, y as f32 - 9.5)))
+            .map(|(x, y)| (1.0 - (x * x + y * y).sqrt() / 8.0).clamp(0.0, 1.0))
+            .collect::<ChannelBuilder>()
+            .build(size);
+        vec![(image::ALPHA.to_string(), channel)]
+            .into_iter()
+            .collect()
+    }
+
+    // Renders each size over the same window
+    fn morphed(amounts: &[f32], amount: f32) -> Vec<f32> {
+        let engine = Engine::new();
+        let ctx = Context {
+            colors: &engine.colors,
+            luts: &engine.luts,
+            region: None,
+            frame: 0,
+            format: engine.format,
+            formats: &engine.formats,
+        };
+        let matte = matte();
+        let controls = [Value::Real(1.0), Value::Text(image::ALPHA.into())];
+        let out = morph(&[Some(&matte)], &controls, &ctx, amount).unwrap();
+        let reach = amounts.iter().fold(0.0f32, |a, b| a.max(b.abs())).ceil() as isize;
+        let window = matte.data_window().grow(Vec2I::new(reach, reach));
+        out.reframed(window)[0].elements().copied().collect()
+    }
+
+    #[test]
+    fn fractional_sizes_fall_between() {
+        for amounts in &[[2.0, 2.5, 3.0], [-2.0, -2.5, -3.0], [0.0, 0.25, 1.0]] {
+            let [lo, mid, hi] = amounts.map(|amount| morphed(amounts, amount));
+            let mut between = false;
+            for ((lo, mid), hi) in lo.iter().zip(mid.iter()).zip(hi.iter()) {
+                assert!(*mid >= lo.min(*hi) - 1e-6 && *mid <= lo.max(*hi) + 1e-6);
+                between |= mid != lo && mid != hi;
+            }
+            assert!(between, "{:?}", amounts);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{
+    control,
+    image::{Image, RGBA},
+    plugin::{self, *},
+    utils::Value,
+};
+use rayon::prelude::*;
+
+// Each of these has a control per color channel
+enum Parameters {
+    Blackpoint,
+    Whitepoint,
+    Lift,
+    Gain,
+    Multiply,
+    Offset,
+    Gamma,
+}
+
+enum Toggles {
+    ClampBlack,
+    ClampWhite,
+}
+
+const PARAMETERS: [([&str; 3], f32); 7] = [
+    (["blackpoint_r", "blackpoint_g", "blackpoint_b"], 0.0),
+    (["whitepoint_r", "whitepoint_g", "whitepoint_b"], 1.0),
+    (["lift_r", "lift_g", "lift_b"], 0.0),
+    (["gain_r", "gain_g", "gain_b"], 1.0),
+    (["multiply_r", "multiply_g", "multiply_b"], 1.0),
+    (["offset_r", "offset_g", "offset_b"], 0.0),
+    (["gamma_r", "gamma_g", "gamma_b"], 1.0),
+];
+
+pub fn create() -> Plugin {
+    let mut controls = PARAMETERS
+        .iter()
+        .flat_map(|(names, default)| {
+            names
+                .iter()
+                .map(move |name| control::Desc::new(name, Value::Real(*default)))
+        })
+        .collect::<Vec<_>>();
+    controls.push(control::Desc::new("clamp_black", Value::Boolean(true)));
+    controls.push(control::Desc::new("clamp_white", Value::Boolean(false)));
+    let desc = plugin::Desc::new("grade", &["bg"], &controls);
+    Plugin::new(render, desc)
+}
+
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => bg,
+        None => return Err(String::from("Invalid background input")),
+    };
+
+    let toggle = |toggle: Toggles| controls[PARAMETERS.len() * 3 + toggle as usize].as_bool();
+    let clamp_black = toggle(Toggles::ClampBlack);
+    let clamp_white = toggle(Toggles::ClampWhite);
+
+    let names = bg.names().collect::<Vec<_>>();
+    let mut out = bg.clone_region(ctx.region);
+    out.par_channels_mut()
+        .zip(names.par_iter())
+        .filter_map(|(channel, name)| {
+            RGBA[..3]
+                .iter()
+                .position(|color| color == name)
+                .map(|channel_index| (channel_index, channel))
+        })
+        .for_each(|(channel_index, channel)| {
+            let param = |param: Parameters| controls[param as usize * 3 + channel_index].as_real();
+            let blackpoint = param(Parameters::Blackpoint);
+            let whitepoint = param(Parameters::Whitepoint);
+            let lift = param(Parameters::Lift);
+            let gain = param(Parameters::Gain);
+            let multiply = param(Parameters::Multiply);
+            let offset = param(Parameters::Offset);
+            let gamma = param(Parameters::Gamma);
+
+            // The conventional grade: blackpoint maps to lift
+            // and whitepoint to gain before multiply and offset
+            let range = whitepoint - blackpoint;
+            let slope = if range.abs() < f32::EPSILON {
+                0.0
+            } else {
+                multiply * (gain - lift) / range
+            };
+            let intercept = offset + lift - slope * blackpoint;
+            let exponent = if gamma.abs() < f32::EPSILON {
+                1.0
+            } else {
+                gamma.recip()
+            };
+
+            channel.par_region_lines_mut(None).for_each(|line| {
+                for element in line.iter_mut() {
+                    let mut value = slope * *element + intercept;
+                    // Negative values have no sensible power
+                    if value > 0.0 && (exponent - 1.0).abs() > f32::EPSILON {
+                        value = value.powf(exponent);
+                    }
+                    if clamp_black {
+                        value = value.max(0.0);
+                    }
+                    if clamp_white {
+                        value = value.min(1.0);
+                    }
+                    *element = value;
+                }
+            });
+        });
+
+    Ok(out)
+}
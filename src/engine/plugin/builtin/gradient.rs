@@ -0,0 +1,7 @@
+use super::ramp::named;
+use crate::plugin::*;
+
+// Another name for ramp
+pub fn create() -> Plugin {
+    named("gradient")
+}
@@ -0,0 +1,163 @@
+use crate::{
+    image::{self, Channel, Image},
+    plugin::{self, *},
+    utils::Value,
+};
+use rayon::prelude::*;
+
+enum Parameters {
+    ScreenR,
+    ScreenG,
+    ScreenB,
+    ClipBlack,
+    ClipWhite,
+    Despill,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("screen_r", Value::Real(0.0)),
+        control::Desc::new("screen_g", Value::Real(1.0)),
+        control::Desc::new("screen_b", Value::Real(0.0)),
+        control::Desc::new("clip_black", Value::Real(0.0)),
+        control::Desc::new("clip_white", Value::Real(1.0)),
+        control::Desc::new("despill", Value::Text("green".into())),
+    ];
+    let desc = plugin::Desc::new("keyer", &["bg"], &controls);
+    Plugin::new(render, desc)
+}
+
+// A color difference key. The matte comes from how far the screen
+// channel, green or blue, whichever is strongest in the screen
+// color, rises above the larger of the other two, relative to the
+// screen color itself. Clip black and white stretch the matte so
+// that values below or above them become fully transparent or
+// opaque. Despill holds green or blue down to the average of the
+// other two. The result is unpremultiplied with the matte as alpha.
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => Ok(bg),
+        None => Err("Invalid background input".to_string()),
+    }?;
+
+    let screen = [
+        controls[Parameters::ScreenR as usize].as_real(),
+        controls[Parameters::ScreenG as usize].as_real(),
+        controls[Parameters::ScreenB as usize].as_real(),
+    ];
+    let clip_black = controls[Parameters::ClipBlack as usize].as_real();
+    let clip_white = controls[Parameters::ClipWhite as usize].as_real();
+    let despill = match controls[Parameters::Despill as usize].as_str() {
+        "none" => None,
+        "green" => Some(1),
+        "blue" => Some(2),
+        despill => return Err(format!("Unrecognized despill: {}", despill)),
+    };
+
+    let primary = if screen[2] > screen[1] { 2 } else { 1 };
+    let difference = |rgb: [f32; 3]| {
+        let others = (0..3).filter(|c| *c != primary).map(|c| rgb[c]);
+        rgb[primary] - others.fold(f32::MIN, f32::max)
+    };
+    let strength = difference(screen);
+    if strength <= f32::EPSILON {
+        return Err("Screen color needs more green or blue than its other channels".into());
+    }
+    let range = (clip_white - clip_black).max(f32::EPSILON);
+
+    let mut out = bg.clone_region(ctx.region);
+    out.unpremultiply();
+    let mut alpha = Channel::black(out.size());
+    let [r, g, b] = out.rgb_mut().ok_or("No RGB channels")?;
+    r.par_lines_mut()
+        .zip(g.par_lines_mut())
+        .zip(b.par_lines_mut())
+        .zip(alpha.par_lines_mut())
+        .for_each(|(((r, g), b), alpha)| {
+            for (((r, g), b), alpha) in r
+                .iter_mut()
+                .zip(g.iter_mut())
+                .zip(b.iter_mut())
+                .zip(alpha.iter_mut())
+            {
+                let mut rgb = [*r, *g, *b];
+                let matte = 1.0 - difference(rgb) / strength;
+                *alpha = ((matte - clip_black) / range).clamp(0.0, 1.0);
+                if let Some(spill) = despill {
+                    let others = (0..3).filter(|c| *c != spill).map(|c| rgb[c]);
+                    rgb[spill] = rgb[spill].min(others.sum::<f32>() * 0.5);
+                }
+                *r = rgb[0];
+                *g = rgb[1];
+                *b = rgb[2];
+            }
+        });
+    out.insert_channel(image::ALPHA.to_string(), alpha);
+    out.set_premultiplied(false);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{engine::Engine, image::ChannelBuilder, utils::Vec2U};
+
+    // Keys a row of pixels against a green screen
+    fn key(pixels: &[[f32; 3]], clip_black: f32, despill: &str) -> Image {
+        let size = Vec2U::new(pixels.len(), 1);
+        let bg = (0..3)
+            .map(|c| {
+                let channel = pixels
+                    .iter()
+                    .map(|rgb| rgb[c])
+                    .collect::<ChannelBuilder>()
+                    .build(size);
+                (image::RGBA[c].to_string(), channel)
+            })
+            .collect::<Image>();
+        let engine = Engine::new();
+        let ctx = Context {
+            colors: &engine.colors,
+            luts: &engine.luts,
+            region: None,
+            frame: 0,
+            format: engine.format,
+            formats: &engine.formats,
+        };
+        let mut controls = create().desc().controls();
+        controls[Parameters::ClipBlack as usize] = Value::Real(clip_black);
+        controls[Parameters::Despill as usize] = Value::Text(despill.into());
+        render(&[Some(&bg)], &controls, &ctx).unwrap()
+    }
+
+    fn alpha(image: &Image) -> Vec<f32> {
+        let alpha = image.channel_by_name(image::ALPHA).unwrap();
+        alpha.elements().copied().collect()
+    }
+
+    #[test]
+    fn screen_is_transparent_and_foreground_opaque() {
+        let out = key(
+            &[[0.0, 1.0, 0.0], [0.8, 0.3, 0.2], [0.2, 0.6, 0.2]],
+            0.0,
+            "none",
+        );
+        for (alpha, expected) in alpha(&out).iter().zip(&[0.0, 1.0, 0.6]) {
+            assert!((alpha - expected).abs() < 1e-6, "{} != {}", alpha, expected);
+        }
+        assert!(!out.is_premultiplied());
+    }
+
+    #[test]
+    fn clip_black_stretches_the_matte() {
+        let out = key(&[[0.2, 0.6, 0.2]], 0.5, "none");
+        assert!((alpha(&out)[0] - 0.2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn despill_holds_green_down() {
+        let out = key(&[[0.2, 0.9, 0.3], [0.8, 0.3, 0.2]], 0.0, "green");
+        let green = out.channel_by_name(image::GREEN).unwrap();
+        assert_eq!(green.elements().copied().collect::<Vec<_>>(), [0.25, 0.3]);
+    }
+}
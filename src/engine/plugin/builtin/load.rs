@@ -1,4 +1,5 @@
 use crate::{
+    color::SCENE_LINEAR,
     control,
     image::Image,
     plugin::*,
@@ -8,15 +9,30 @@ use std::path::PathBuf;
 
 enum Parameters {
     Filename,
+    Colorspace,
+    PixelAspect,
 }
 
 pub fn create() -> Plugin {
-    let controls = [control::Desc::new("filename", Value::Text("".into()))];
+    let controls = [
+        control::Desc::file("filename"),
+        control::Desc::new("colorspace", Value::Text("srgb".into())),
+        control::Desc::new("pixel_aspect", Value::Real(0.0)),
+    ];
     let desc = plugin::Desc::new("load", &[], &controls);
-    Plugin::new(render, desc)
+    Plugin::new(render, desc).color_managed()
 }
 
-fn render(_: Inputs, controls: Controls) -> Result<Image, String> {
+// A pixel aspect of zero keeps the one stored in the file
+fn render(_: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
     let path = PathBuf::from(controls[Parameters::Filename as usize].as_str());
-    io::load(&path)
+    let colorspace = controls[Parameters::Colorspace as usize].as_str();
+    let processor = ctx.colors.processor(colorspace, SCENE_LINEAR)?;
+    let mut image = io::load_region(&path, ctx.region)?;
+    processor.apply_region(&mut image, ctx.region);
+    let pixel_aspect = controls[Parameters::PixelAspect as usize].as_real();
+    if pixel_aspect > 0.0 {
+        image.set_pixel_aspect(pixel_aspect);
+    }
+    Ok(image)
 }
@@ -0,0 +1,64 @@
+use super::adjust::{KB, KG, KR};
+use crate::{
+    image::{self, Channel, Image},
+    plugin::{self, *},
+    utils::Value,
+};
+use rayon::prelude::*;
+
+enum Parameters {
+    Low,
+    High,
+    Invert,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("low", Value::Real(0.0)),
+        control::Desc::new("high", Value::Real(1.0)),
+        control::Desc::new("invert", Value::Boolean(false)),
+    ];
+    let desc = plugin::Desc::new("luma_key", &["bg"], &controls);
+    Plugin::new(render, desc)
+}
+
+// The matte rises from zero at a luminance of low to one at
+// high, with the same weights as adjust. Other channels pass
+// through, unpremultiplied.
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => Ok(bg),
+        None => Err("Invalid background input".to_string()),
+    }?;
+
+    let low = controls[Parameters::Low as usize].as_real();
+    let high = controls[Parameters::High as usize].as_real();
+    let invert = controls[Parameters::Invert as usize].as_bool();
+    let range = high - low;
+
+    let mut out = bg.clone_region(ctx.region);
+    out.unpremultiply();
+    let [r, g, b] = out.rgb().ok_or("No RGB channels")?;
+    let mut matte = Channel::black(out.size());
+    matte
+        .par_lines_mut()
+        .zip(r.par_lines())
+        .zip(g.par_lines())
+        .zip(b.par_lines())
+        .for_each(|(((matte, r), g), b)| {
+            for (((matte, r), g), b) in matte.iter_mut().zip(r).zip(g).zip(b) {
+                let luma = r * KR + g * KG + b * KB;
+                let key = if range.abs() > f32::EPSILON {
+                    ((luma - low) / range).clamp(0.0, 1.0)
+                } else if luma >= low {
+                    1.0
+                } else {
+                    0.0
+                };
+                *matte = if invert { 1.0 - key } else { key };
+            }
+        });
+    out.insert_channel(image::ALPHA.to_string(), matte);
+    out.set_premultiplied(false);
+    Ok(out)
+}
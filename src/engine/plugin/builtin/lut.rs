@@ -0,0 +1,38 @@
+use crate::{
+    color::{lut::Interpolation, Processor, Transform},
+    control,
+    image::Image,
+    plugin::{self, *},
+    utils::Value,
+};
+use std::path::Path;
+
+enum Parameters {
+    Filename,
+    Interpolation,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::file("filename"),
+        control::Desc::new("interpolation", Value::Text("trilinear".into())),
+    ];
+    let desc = plugin::Desc::new("lut", &["bg"], &controls);
+    Plugin::new(render, desc)
+}
+
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => bg,
+        None => return Err(String::from("Invalid background input")),
+    };
+
+    let path = Path::new(controls[Parameters::Filename as usize].as_str());
+    let interpolation =
+        Interpolation::parse(controls[Parameters::Interpolation as usize].as_str())?;
+    let lut = ctx.luts.load(path)?;
+
+    let mut out = bg.clone_region(ctx.region);
+    Processor::new(vec![Transform::Lut(lut, interpolation)]).apply_region(&mut out, ctx.region);
+    Ok(out)
+}
@@ -0,0 +1,98 @@
+use crate::{
+    control,
+    image::{Channel, Image},
+    plugin::{self, *},
+    utils::{distance, Rect, Value, Vec2I},
+};
+use rayon::prelude::*;
+
+enum Parameters {
+    Amount,
+    Softness,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("amount", Value::Real(1.0)),
+        control::Desc::new("softness", Value::Real(0.0)),
+    ];
+    let desc = plugin::Desc::new("matte_choke", &["bg"], &controls);
+    Plugin::new(render, desc).with_roi(roi)
+}
+
+fn roi(_: usize, region: Rect, controls: Controls) -> Option<Rect> {
+    let reach = reach(extent(controls));
+    Some(region.grow(Vec2I::new(reach, reach)))
+}
+
+// Pulls the edge of the alpha in by the amount in pixels, or
+// pushes it out when negative, and spreads the transition across
+// the softness. The edge is found where the alpha crosses one half
+// and rebuilt from there, so any softness the matte had beyond a
+// pixel gives way to the new one. Color keeps its alpha state, so
+// a premultiplied image has its color scaled to match the new alpha.
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => Ok(bg),
+        None => Err(String::from("Invalid background input")),
+    }?;
+    let amount = controls[Parameters::Amount as usize].as_real();
+    let softness = controls[Parameters::Softness as usize].as_real().max(0.0);
+
+    let reach = reach(extent(controls));
+    let mut out = bg.reframed(bg.data_window().grow(Vec2I::new(reach, reach)));
+    let premultiplied = out.is_premultiplied();
+    out.unpremultiply();
+    let alpha = out.alpha_index().ok_or("No alpha channel")?;
+    let signed = signed_distance(&out[alpha]);
+    out[alpha]
+        .par_elements_mut()
+        .zip(signed.par_iter())
+        .for_each(|(element, signed)| {
+            *element = ((signed - amount) / (softness + 1.0) + 0.5).clamp(0.0, 1.0);
+        });
+    if premultiplied {
+        out.premultiply();
+    }
+    Ok(out.clone_region(ctx.region))
+}
+
+// How far from the original edge the new one can reach
+fn extent(controls: Controls) -> f32 {
+    let amount = controls[Parameters::Amount as usize].as_real();
+    let softness = controls[Parameters::Softness as usize].as_real().max(0.0);
+    amount.abs() + softness
+}
+
+// Distance from each pixel center to where the channel crosses
+// one half, positive inside. Pixels along the edge take their
+// distance from their own value, which keeps soft edges intact.
+fn signed_distance(channel: &Channel) -> Vec<f32> {
+    let size = channel.size();
+    let inside = |i: usize| channel[i] >= 0.5;
+    let to_outside = distance::squared(size, |i| !inside(i));
+    let to_inside = distance::squared(size, inside);
+    (0..size.x * size.y)
+        .into_par_iter()
+        .map(|i| {
+            let distance = if inside(i) {
+                to_outside[i]
+            } else {
+                to_inside[i]
+            };
+            if distance <= 1.0 {
+                channel[i] - 0.5
+            } else if inside(i) {
+                distance.sqrt() - 0.5
+            } else {
+                0.5 - distance.sqrt()
+            }
+        })
+        .collect()
+}
+
+// How far the pixels that make up the result may lie
+// from it, one for the edge and one for rounding
+fn reach(amount: f32) -> isize {
+    amount.abs().ceil() as isize + 2
+}
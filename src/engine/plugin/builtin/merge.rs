@@ -1,32 +1,46 @@
 use crate::{
     control,
-    image::Image,
+    image::{self, Channel, Image},
     plugin::{self, *},
-    utils::{Value, Vec2I, Vec2U},
+    utils::{Rect, Value, Vec2I, Vec2U},
 };
 use rayon::prelude::*;
 
 enum Parameters {
     TranslateX,
     TranslateY,
+    Layer,
 }
 
 pub fn create() -> Plugin {
     let controls = [
         control::Desc::new("tx", Value::Integer(0)),
         control::Desc::new("ty", Value::Integer(0)),
+        control::Desc::new("layer", Value::Text("rgba".into())),
     ];
     let desc = plugin::Desc::new("merge", &["bg", "fg"], &controls);
-    Plugin::new(render, desc)
+    Plugin::new(render, desc).with_roi(roi)
 }
 
-fn render(inputs: Inputs, controls: Controls) -> Result<Image, String> {
+fn roi(input: usize, region: Rect, controls: Controls) -> Option<Rect> {
+    let tx = controls[Parameters::TranslateX as usize].as_int();
+    let ty = controls[Parameters::TranslateY as usize].as_int();
+    match input {
+        0 => Some(region),
+        _ => Some(region.translate(Vec2I::new(-tx, -ty))),
+    }
+}
+
+// Composites the channels of one foreground layer over the
+// background channels with the same names. Other background
+// layers pass through.
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
     let bg = match inputs[0] {
         Some(bg) => bg,
         None => return Err(String::from("Invalid background input")),
     };
 
-    let mut out = bg.clone();
+    let mut out = bg.clone_region(ctx.region);
 
     let fg = match inputs[1] {
         Some(fg) => fg,
@@ -37,45 +51,68 @@ fn render(inputs: Inputs, controls: Controls) -> Result<Image, String> {
     let tx = controls[Parameters::TranslateX as usize].as_int();
     let ty = controls[Parameters::TranslateY as usize].as_int();
     let translate = Vec2I::new(tx, ty);
+    let layer = controls[Parameters::Layer as usize].as_str();
+
+    for i in fg.layer(layer) {
+        let name = fg.name(i);
+        if out.index_of_channel(name).is_none() {
+            // A background without alpha is opaque
+            let fill = if name == image::ALPHA { 1.0 } else { 0.0 };
+            out.insert_channel(name.into(), Channel::filled(out.size(), fill));
+        }
+    }
+
+    // Grow to hold foreground pixels that move outside the background
+    let fg_window = fg.data_window().translate(translate);
+    let needed = ctx
+        .region
+        .map_or(fg_window, |region| fg_window.intersect(region));
+    let data = out.data_window().union(needed);
+    if data != out.data_window() {
+        out = out.reframed(data);
+    }
+    let offset = fg_window.min - data.min;
+    let out_names = out.names().map(String::from).collect::<Vec<_>>();
+
+    if let Some(fg_alpha) = fg.alpha_index() {
+        // Alpha blended, which is only correct on premultiplied color.
+        // The result is returned in the same state as the background.
+        let mut fg = fg.clone();
+        fg.premultiply();
+        let bg_premultiplied = out.is_premultiplied();
+        out.premultiply();
 
-    if fg.channel_count() == 4 {
-        // Alpha blended
-        fg.par_channels()
-            .take(3)
+        let fg_a = &fg[fg_alpha];
+        out.par_channels_mut()
+            .zip(out_names.par_iter())
+            .filter(|(_, name)| image::layer_of(name) == layer)
+            .filter_map(|(out_chan, name)| fg.channel_by_name(name).map(|fg| (fg, out_chan)))
             // Would it be better to parallelize over lines?
-            .zip(out.par_channels_mut())
             .for_each(|(fg_chan, out_chan)| {
-                for (y, (fg_line, alpha_line)) in fg_chan.lines().zip(fg[3].lines()).enumerate() {
+                for (y, (fg_line, alpha_line)) in fg_chan.lines().zip(fg_a.lines()).enumerate() {
                     for (x, (fg_e, alpha_e)) in fg_line.iter().zip(alpha_line.iter()).enumerate() {
-                        let pos = translate + Vec2U::new(x, y).into();
+                        let pos = offset + Vec2U::new(x, y).into();
                         if let Some(index) = out_chan.index_of(pos) {
                             let bg_e = out_chan[index];
-                            let value = *fg_e * alpha_e + bg_e * (1.0 - *alpha_e);
-                            out_chan[index] = value;
+                            out_chan[index] = *fg_e + bg_e * (1.0 - *alpha_e);
                         }
                     }
                 }
             });
 
-        let out_a = &mut out[3];
-        for (y, fg_line) in fg[3].lines().enumerate() {
-            for (x, fg_e) in fg_line.iter().enumerate() {
-                let pos = translate + Vec2U::new(x, y).into();
-                if let Some(index) = out_a.index_of(pos) {
-                    let bg_e = out_a[index];
-                    let value = 1.0 - (1.0 - fg_e) * (1.0 - bg_e);
-                    out_a[index] = value;
-                }
-            }
+        if !bg_premultiplied {
+            out.unpremultiply();
         }
     } else {
         // Straight copy
-        fg.par_channels()
-            .zip(out.par_channels_mut())
+        out.par_channels_mut()
+            .zip(out_names.par_iter())
+            .filter(|(_, name)| image::layer_of(name) == layer)
+            .filter_map(|(out_c, name)| fg.channel_by_name(name).map(|fg| (fg, out_c)))
             .for_each(|(fg_c, out_c)| {
                 for (y, fg_line) in fg_c.lines().enumerate() {
                     for (x, fg_e) in fg_line.iter().enumerate() {
-                        let pos = translate + Vec2U::new(x, y).into();
+                        let pos = offset + Vec2U::new(x, y).into();
                         if let Some(index) = out_c.index_of(pos) {
                             out_c[index] = *fg_e;
                         }
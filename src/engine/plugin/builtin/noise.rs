@@ -0,0 +1,87 @@
+use super::constant::frame_format;
+use crate::{
+    control,
+    image::{self, Channel, Image},
+    plugin::{self, *},
+    utils::{
+        noise::{self, Kind},
+        Rect, Value,
+    },
+};
+use rayon::prelude::*;
+
+enum Parameters {
+    Width,
+    Height,
+    Kind,
+    Scale,
+    Octaves,
+    Lacunarity,
+    Gain,
+    Seed,
+    OffsetX,
+    OffsetY,
+    Evolution,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("width", Value::Integer(0)),
+        control::Desc::new("height", Value::Integer(0)),
+        control::Desc::new("kind", Value::Text("perlin".into())),
+        control::Desc::new("scale", Value::Real(64.0)),
+        control::Desc::new("octaves", Value::Integer(4)),
+        control::Desc::new("lacunarity", Value::Real(2.0)),
+        control::Desc::new("gain", Value::Real(0.5)),
+        control::Desc::new("seed", Value::Integer(0)),
+        control::Desc::new("offset_x", Value::Real(0.0)),
+        control::Desc::new("offset_y", Value::Real(0.0)),
+        control::Desc::new("evolution", Value::Real(0.0)),
+    ];
+    let desc = plugin::Desc::new("noise", &[], &controls);
+    Plugin::new(render, desc).animated().format_dependent()
+}
+
+// Scale is the size of the largest features in pixels. Evolution
+// is how far the noise moves through its third dimension each
+// frame, with zero holding it still. Every channel gets the noise.
+fn render(_: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let format = frame_format(
+        controls,
+        Parameters::Width as usize,
+        Parameters::Height as usize,
+        ctx,
+    );
+    let kind = controls[Parameters::Kind as usize].as_str();
+    let kind = Kind::from_name(kind).ok_or(format!("Unrecognized noise kind: {}", kind))?;
+    let scale = controls[Parameters::Scale as usize]
+        .as_real()
+        .max(f32::EPSILON);
+    let octaves = controls[Parameters::Octaves as usize].as_uint();
+    let lacunarity = controls[Parameters::Lacunarity as usize].as_real();
+    let gain = controls[Parameters::Gain as usize].as_real();
+    let seed = controls[Parameters::Seed as usize].as_int() as u32;
+    let offset_x = controls[Parameters::OffsetX as usize].as_real();
+    let offset_y = controls[Parameters::OffsetY as usize].as_real();
+    let z = ctx.frame as f32 * controls[Parameters::Evolution as usize].as_real();
+
+    let frame = Rect::from_size(format.size());
+    let data = ctx.region.map_or(frame, |region| region.intersect(frame));
+    let mut channel = Channel::black(data.size());
+    channel.par_lines_mut().enumerate().for_each(|(y, line)| {
+        let y = ((data.min.y + y as isize) as f32 + 0.5 + offset_y) / scale;
+        for (x, element) in line.iter_mut().enumerate() {
+            let x = ((data.min.x + x as isize) as f32 + 0.5 + offset_x) / scale;
+            *element = noise::fractal(kind, [x, y, z], octaves, lacunarity, gain, seed);
+        }
+    });
+
+    let mut image = image::RGBA
+        .iter()
+        .map(|name| (name.to_string(), channel.clone()))
+        .collect::<Image>();
+    image.set_data_origin(data.min);
+    image.set_display_window(frame);
+    image.set_pixel_aspect(format.pixel_aspect);
+    Ok(image)
+}
@@ -0,0 +1,110 @@
+use super::constant::{frame_format, generate};
+use crate::{
+    control,
+    image::Image,
+    plugin::{self, *},
+    utils::{Value, Vec2F},
+};
+
+enum Parameters {
+    Width,
+    Height,
+    Shape,
+    StartX,
+    StartY,
+    EndX,
+    EndY,
+    StartR,
+    StartG,
+    StartB,
+    StartA,
+    EndR,
+    EndG,
+    EndB,
+    EndA,
+}
+
+pub fn create() -> Plugin {
+    named("ramp")
+}
+
+// Gradient is the same plugin under another name
+pub(crate) fn named(name: &str) -> Plugin {
+    let controls = [
+        control::Desc::new("width", Value::Integer(0)),
+        control::Desc::new("height", Value::Integer(0)),
+        control::Desc::new("shape", Value::Text("linear".into())),
+        control::Desc::new("start_x", Value::Real(0.0)),
+        control::Desc::new("start_y", Value::Real(0.0)),
+        control::Desc::new("end_x", Value::Real(512.0)),
+        control::Desc::new("end_y", Value::Real(0.0)),
+        control::Desc::new("start_r", Value::Real(0.0)),
+        control::Desc::new("start_g", Value::Real(0.0)),
+        control::Desc::new("start_b", Value::Real(0.0)),
+        control::Desc::new("start_a", Value::Real(1.0)),
+        control::Desc::new("end_r", Value::Real(1.0)),
+        control::Desc::new("end_g", Value::Real(1.0)),
+        control::Desc::new("end_b", Value::Real(1.0)),
+        control::Desc::new("end_a", Value::Real(1.0)),
+    ];
+    let desc = plugin::Desc::new(name, &[], &controls);
+    Plugin::new(render, desc).format_dependent()
+}
+
+// Linear ramps blend along the line from start to end and
+// hold their colors past either point. Radial ones blend
+// outward from the start, reaching the end color at the
+// distance between the points.
+fn render(_: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let format = frame_format(
+        controls,
+        Parameters::Width as usize,
+        Parameters::Height as usize,
+        ctx,
+    );
+    let point = |x: Parameters, y: Parameters| {
+        Vec2F::new(
+            controls[x as usize].as_real(),
+            controls[y as usize].as_real(),
+        )
+    };
+    let start = point(Parameters::StartX, Parameters::StartY);
+    let end = point(Parameters::EndX, Parameters::EndY);
+    let start_color = [
+        Parameters::StartR,
+        Parameters::StartG,
+        Parameters::StartB,
+        Parameters::StartA,
+    ]
+    .map(|channel| controls[channel as usize].as_real());
+    let end_color = [
+        Parameters::EndR,
+        Parameters::EndG,
+        Parameters::EndB,
+        Parameters::EndA,
+    ]
+    .map(|channel| controls[channel as usize].as_real());
+
+    let axis = Vec2F::new(end.x - start.x, end.y - start.y);
+    let length2 = axis.length2().max(f32::EPSILON);
+    let radial = match controls[Parameters::Shape as usize].as_str() {
+        "linear" => false,
+        "radial" => true,
+        shape => return Err(format!("Unrecognized ramp shape: {}", shape)),
+    };
+
+    Ok(generate(format, ctx.region, |pos| {
+        let offset = Vec2F::new(pos.x - start.x, pos.y - start.y);
+        let t = if radial {
+            (offset.length2() / length2).sqrt()
+        } else {
+            (offset.x * axis.x + offset.y * axis.y) / length2
+        };
+        let t = t.clamp(0.0, 1.0);
+        let mut out = [0.0; 4];
+        for (i, element) in out.iter_mut().enumerate() {
+            *element = start_color[i] + (end_color[i] - start_color[i]) * t;
+        }
+        out
+    }))
+}
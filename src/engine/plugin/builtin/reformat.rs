@@ -0,0 +1,57 @@
+use super::resize::{resample, Filter, Layout};
+use crate::{
+    control,
+    image::Image,
+    plugin::{self, *},
+    utils::{Rect, Value},
+};
+
+enum Parameters {
+    Format,
+    Mode,
+    Filter,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("format", Value::Text("".into())),
+        control::Desc::new("mode", Value::Text("fit".into())),
+        control::Desc::new("filter", Value::Text("catmull_rom".into())),
+    ];
+    let desc = plugin::Desc::new("reformat", &["bg"], &controls);
+    Plugin::new(render, desc).with_roi(roi).format_dependent()
+}
+
+// The scale depends on the input frame size
+fn roi(_: usize, _: Rect, _: Controls) -> Option<Rect> {
+    None
+}
+
+// Scales the frame into a named or custom format, or the project
+// format when none is given. Fit and fill keep the picture's shape
+// on screen, so they account for how wide the pixels are on either
+// side. Distort stretches the frame to the format as pixels.
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => Ok(bg),
+        None => Err(String::from("Invalid background input")),
+    }?;
+
+    let format = match controls[Parameters::Format as usize].as_str() {
+        "" => ctx.format,
+        spec => ctx.formats.lookup(spec)?,
+    };
+    let filter = Filter::from_control(&controls[Parameters::Filter as usize])?;
+
+    let src = bg.display_window().size();
+    let layout = match controls[Parameters::Mode as usize].as_str() {
+        "fit" => Layout::fitted(format, src, bg.pixel_aspect(), false),
+        "fill" => Layout::fitted(format, src, bg.pixel_aspect(), true),
+        "distort" => Layout::stretched(format.size(), src),
+        mode => return Err(format!("Unrecognized reformat mode: {}", mode)),
+    };
+
+    let mut out = resample(bg, &layout, filter, ctx.region);
+    out.set_pixel_aspect(format.pixel_aspect);
+    Ok(out)
+}
@@ -1,119 +1,376 @@
 use crate::{
     control,
+    format::Format,
     image::{Channel, Image},
     plugin::{self, *},
-    utils::{Value, Vec2U},
+    utils::{Rect, Value, Vec2I, Vec2U},
 };
 use rayon::prelude::*;
-use std::cmp::{max, min};
+use std::{
+    cmp::{max, min},
+    f32::consts::PI,
+    ops::Range,
+};
 
 enum Parameters {
-    SizeX,
-    SizeY,
+    Width,
+    Height,
+    Mode,
+    Scale,
+    Filter,
+    ProjectFormat,
 }
 
 pub fn create() -> Plugin {
     let controls = [
         control::Desc::new("width", Value::Integer(512)),
         control::Desc::new("height", Value::Integer(512)),
+        control::Desc::new("mode", Value::Text("size".into())),
+        control::Desc::new("scale", Value::Real(1.0)),
+        control::Desc::new("filter", Value::Text("catmull_rom".into())),
+        control::Desc::new("project_format", Value::Boolean(false)),
     ];
     let desc = plugin::Desc::new("resize", &["bg"], &controls);
-    Plugin::new(render, desc)
+    Plugin::new(render, desc).with_roi(roi).format_dependent()
+}
+
+// The scale depends on the input frame size
+fn roi(_: usize, _: Rect, _: Controls) -> Option<Rect> {
+    None
 }
 
-fn render(inputs: Inputs, controls: Controls) -> Result<Image, String> {
+// Size stretches the frame to the width and height and scale
+// multiplies both of its sides by the scale. Fit and fill keep
+// the aspect ratio while scaling the frame to lie within or to
+// cover the width and height, centering it there. Fit leaves
+// black bars outside the frame and fill crops it. With project
+// format set, the frame goes to the project format instead of
+// the width and height, taking on the shape of its pixels,
+// otherwise the pixels keep theirs.
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
     let bg = match inputs[0] {
         Some(bg) => Ok(bg),
         None => Err(String::from("Invalid background input")),
     }?;
 
-    let sx = controls[Parameters::SizeX as usize].as_uint();
-    let sy = controls[Parameters::SizeY as usize].as_uint();
+    let filter = Filter::from_control(&controls[Parameters::Filter as usize])?;
+    let (layout, pixel_aspect) = layout(controls, bg, ctx)?;
+    let mut out = resample(bg, &layout, filter, ctx.region);
+    out.set_pixel_aspect(pixel_aspect);
+    Ok(out)
+}
+
+// The layout along with the pixel aspect of the output
+fn layout(controls: Controls, bg: &Image, ctx: &Context) -> Result<(Layout, f32), String> {
+    let src = bg.display_window().size();
+    let format = if controls[Parameters::ProjectFormat as usize].as_bool() {
+        ctx.format
+    } else {
+        let width = controls[Parameters::Width as usize].as_uint();
+        let height = controls[Parameters::Height as usize].as_uint();
+        Format::new(width, height, bg.pixel_aspect())
+    };
+    let layout = match controls[Parameters::Mode as usize].as_str() {
+        "size" => Layout::stretched(format.size(), src),
+        "scale" => {
+            let scale = controls[Parameters::Scale as usize].as_real().max(0.0);
+            let size = Vec2U::new(
+                (src.x as f32 * scale).round() as usize,
+                (src.y as f32 * scale).round() as usize,
+            );
+            return Ok((Layout::stretched(size, src), bg.pixel_aspect()));
+        }
+        "fit" => Layout::fitted(format, src, bg.pixel_aspect(), false),
+        "fill" => Layout::fitted(format, src, bg.pixel_aspect(), true),
+        mode => return Err(format!("Unrecognized resize mode: {}", mode)),
+    };
+    Ok((layout, format.pixel_aspect))
+}
 
-    let h_buf = scale_axis(&bg, sx);
-    let v_buf = scale_axis(&h_buf, sy);
+// Scales the frame of an image to the layout. The scaled frame
+// covers the data window, so anything left over outside it
+// stays black.
+pub(crate) fn resample(bg: &Image, layout: &Layout, filter: Filter, region: Option<Rect>) -> Image {
+    let src_size = bg.display_window().size();
+    let display = Rect::from_size(layout.size);
+    let footprint = Rect::new(
+        Vec2I::new(
+            layout.x.shift.floor() as isize,
+            layout.y.shift.floor() as isize,
+        ),
+        Vec2I::new(
+            (layout.x.shift + src_size.x as f32 * layout.x.scale).ceil() as isize,
+            (layout.y.shift + src_size.y as f32 * layout.y.scale).ceil() as isize,
+        ),
+    );
+    let data = footprint.intersect(display);
+    let region = region.map_or(data, |r| r.intersect(data));
 
-    Ok(v_buf)
+    // Scales the frame, so pixels outside it are dropped
+    let frame = bg.reframed(bg.display_window());
+    let mut out = if region.is_empty() || src_size.x == 0 || src_size.y == 0 {
+        let mut empty = frame.reframed(Rect::default());
+        empty.set_premultiplied(frame.is_premultiplied());
+        empty
+    } else {
+        frame.map_premultiplied(|bg| {
+            // Only source rows near those requested feed the second pass
+            let rows = region.rows();
+            let reach = filter.radius() * layout.y.stretch() + 1.0;
+            let lo = (layout.y.source(rows.start) - reach).floor().max(0.0) as usize;
+            let hi = ((layout.y.source(rows.end - 1) + reach).ceil() as usize + 1).min(src_size.y);
+            let axis = Axis::new(src_size.x, 0, layout.x);
+            let h_buf = scale_axis(bg, axis, filter, lo..hi, region.columns());
+            let lines = 0..h_buf.size().y;
+            let axis = Axis::new(src_size.y, lo, layout.y);
+            scale_axis(&h_buf, axis, filter, lines, rows)
+        })
+    };
+    out.set_data_origin(region.min);
+    out.set_display_window(display);
+    out
 }
 
-fn scale_axis(src: &Image, dim: usize) -> Image {
-    if dim > src.desc().size.x {
-        upscale_axis(src, dim)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Filter {
+    Nearest,
+    Box,
+    Bilinear,
+    Mitchell,
+    Lanczos3,
+    CatmullRom,
+}
+
+impl Filter {
+    pub(crate) fn from_control(control: &Value) -> Result<Self, String> {
+        match control.as_str() {
+            "nearest" => Ok(Filter::Nearest),
+            "box" => Ok(Filter::Box),
+            "bilinear" => Ok(Filter::Bilinear),
+            "mitchell" => Ok(Filter::Mitchell),
+            "lanczos3" => Ok(Filter::Lanczos3),
+            "catmull_rom" => Ok(Filter::CatmullRom),
+            filter => Err(format!("Unrecognized filter: {}", filter)),
+        }
+    }
+
+    // How far the filter reaches either side in source pixels
+    // when upscaling. Downscaling stretches it to match.
+    fn radius(self) -> f32 {
+        match self {
+            Filter::Nearest | Filter::Box => 0.5,
+            Filter::Bilinear => 1.0,
+            Filter::Mitchell | Filter::CatmullRom => 2.0,
+            Filter::Lanczos3 => 3.0,
+        }
+    }
+
+    fn weight(self, x: f32) -> f32 {
+        match self {
+            Filter::Nearest | Filter::Box => {
+                if (-0.5..0.5).contains(&x) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Filter::Bilinear => (1.0 - x.abs()).max(0.0),
+            Filter::Mitchell => cubic(x, 1.0 / 3.0, 1.0 / 3.0),
+            Filter::CatmullRom => cubic(x, 0.0, 0.5),
+            Filter::Lanczos3 => {
+                if x.abs() < 3.0 {
+                    sinc(x) * sinc(x / 3.0)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+// The family of cubics from Mitchell and Netravali
+fn cubic(x: f32, b: f32, c: f32) -> f32 {
+    let x = x.abs();
+    let x2 = x * x;
+    let x3 = x2 * x;
+    let w = if x < 1.0 {
+        (12.0 - 9.0 * b - 6.0 * c) * x3 + (-18.0 + 12.0 * b + 6.0 * c) * x2 + (6.0 - 2.0 * b)
+    } else if x < 2.0 {
+        (-b - 6.0 * c) * x3
+            + (6.0 * b + 30.0 * c) * x2
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c)
+    } else {
+        0.0
+    };
+    w / 6.0
+}
+
+fn sinc(x: f32) -> f32 {
+    if x.abs() < f32::EPSILON {
+        1.0
     } else {
-        downscale_axis(src, dim)
+        (PI * x).sin() / (PI * x)
     }
 }
 
-fn upscale_axis(src: &Image, dim: usize) -> Image {
-    let scale_factor = src.desc().size.x as f32 / dim as f32;
-    let dst_size = Vec2U::new(src.desc().size.y, dim);
-    src.par_channels()
-        .map(|src| {
-            let mut dst = Channel::black(dst_size);
-            for x in 0..dst_size.x {
-                for y in 0..dst_size.y {
-                    // dst_y is bigger, downscale to src_y
-                    let pivot = y as f32 * scale_factor;
-                    let frac = pivot.fract();
-
-                    let pivot = pivot as isize;
-                    let srcn = max(0, pivot - 1) as usize;
-                    let pivot = pivot as usize;
-                    let clip = src.size().x - 1;
-                    let src0 = min(clip, pivot);
-                    let src1 = min(clip, pivot + 1);
-                    let src2 = min(clip, pivot + 2);
-
-                    // dst_x maps to src_y directly
-                    let srcy = x * src.size().x;
-
-                    let y0 = src[srcn + srcy];
-                    let y1 = src[src0 + srcy];
-                    let y2 = src[src1 + srcy];
-                    let y3 = src[src2 + srcy];
-
-                    let a0 = -0.5 * y0 + 1.5 * y1 - 1.5 * y2 + 0.5 * y3;
-                    let a1 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
-                    let a2 = -0.5 * y0 + 0.5 * y2;
-                    let a3 = y1;
-
-                    let frac2 = frac * frac;
-                    let out_index = y * dst_size.x + x;
-                    dst[out_index] = a0 * frac2 * frac + a1 * frac2 + a2 * frac + a3;
-                }
+// How one axis of the frame maps onto the output, as the output
+// pixels per source pixel and the output position of the source's
+// first edge
+#[derive(Copy, Clone, Debug)]
+struct Mapping {
+    scale: f32,
+    shift: f32,
+}
+
+impl Mapping {
+    // The source position under the center of an output pixel
+    fn source(&self, i: usize) -> f32 {
+        (i as f32 + 0.5 - self.shift) / self.scale - 0.5
+    }
+
+    // Downscaling widens the filter to cover
+    // every source pixel under an output one
+    fn stretch(&self) -> f32 {
+        self.scale.recip().max(1.0)
+    }
+}
+
+pub(crate) struct Layout {
+    size: Vec2U,
+    x: Mapping,
+    y: Mapping,
+}
+
+impl Layout {
+    // Stretches a frame of the source size to the size
+    pub(crate) fn stretched(size: Vec2U, src: Vec2U) -> Self {
+        Self {
+            size,
+            x: Mapping {
+                scale: size.x as f32 / src.x.max(1) as f32,
+                shift: 0.0,
+            },
+            y: Mapping {
+                scale: size.y as f32 / src.y.max(1) as f32,
+                shift: 0.0,
+            },
+        }
+    }
+
+    // Scales a frame of the source size to lie within the format,
+    // or to cover it when filling, and centers it there. The frame
+    // keeps its shape as seen, so when the format's pixels are a
+    // different shape from the source's it is squeezed to suit.
+    pub(crate) fn fitted(format: Format, src: Vec2U, pixel_aspect: f32, fill: bool) -> Self {
+        let fit_x = format.width as f32 / src.x.max(1) as f32;
+        let fit_y = format.height as f32 / src.y.max(1) as f32;
+        // How much wider the format's pixels are than the source's
+        let squeeze = format.pixel_aspect / pixel_aspect;
+        let scale = if fill {
+            (fit_x * squeeze).max(fit_y)
+        } else {
+            (fit_x * squeeze).min(fit_y)
+        };
+        Self::centered(format.size(), src, scale / squeeze, scale)
+    }
+
+    // Scales a frame of the source size by each
+    // factor and centers it within the size
+    fn centered(size: Vec2U, src: Vec2U, scale_x: f32, scale_y: f32) -> Self {
+        let mapping = |size: usize, src: usize, scale: f32| {
+            let scale = scale.max(f32::EPSILON);
+            Mapping {
+                scale,
+                shift: (size as f32 - src.max(1) as f32 * scale) / 2.0,
             }
-            dst
-        })
-        .collect::<Image>()
+        };
+        Self {
+            size,
+            x: mapping(size.x, src.x, scale_x),
+            y: mapping(size.y, src.y, scale_y),
+        }
+    }
+}
+
+// The source lines may hold only part of
+// the full length, starting at the offset
+#[derive(Copy, Clone)]
+struct Axis {
+    len: usize,
+    offset: usize,
+    mapping: Mapping,
+}
+
+impl Axis {
+    fn new(len: usize, offset: usize, mapping: Mapping) -> Self {
+        Self {
+            len,
+            offset,
+            mapping,
+        }
+    }
+
+    // Clamps a position along the full length to a stored element
+    fn element(&self, i: isize, stored: usize) -> usize {
+        let i = min(self.len as isize - 1, max(0, i)) as usize;
+        min(stored - 1, i.saturating_sub(self.offset))
+    }
+
+    // The stored elements and normalized weights making
+    // up an output pixel. Nearest takes a single element.
+    fn taps(&self, i: usize, filter: Filter, stored: usize) -> Vec<(usize, f32)> {
+        let center = self.mapping.source(i);
+        if filter == Filter::Nearest {
+            return vec![(self.element(center.round() as isize, stored), 1.0)];
+        }
+        let stretch = self.mapping.stretch();
+        let reach = filter.radius() * stretch;
+        let lo = (center - reach).floor() as isize;
+        let hi = (center + reach).ceil() as isize;
+        let mut taps = (lo..=hi)
+            .map(|j| {
+                let weight = filter.weight((j as f32 - center) / stretch);
+                (self.element(j, stored), weight)
+            })
+            .filter(|(_, weight)| *weight != 0.0)
+            .collect::<Vec<_>>();
+        let total = taps.iter().map(|(_, weight)| weight).sum::<f32>();
+        if total.abs() > f32::EPSILON {
+            for (_, weight) in taps.iter_mut() {
+                *weight /= total;
+            }
+        }
+        taps
+    }
 }
 
-fn downscale_axis(src: &Image, dim: usize) -> Image {
-    let dst_size = Vec2U::new(src.desc().size.y, dim);
-    let width = src.desc().size.x as f32 / dim as f32;
+// Scales lines of the source along the axis and returns the
+// transpose, holding only the given lines and span of each line.
+// Each pass decides for itself whether it grows or shrinks.
+fn scale_axis(
+    src: &Image,
+    axis: Axis,
+    filter: Filter,
+    lines: Range<usize>,
+    span: Range<usize>,
+) -> Image {
+    let dst_size = Vec2U::new(lines.len(), span.len());
+    let stored = src.size().x;
+    let taps = span
+        .map(|i| axis.taps(i, filter, stored))
+        .collect::<Vec<_>>();
     src.par_channels()
         .map(|src| {
             // Starting with x, which is out-of-order. However, since
             // dst is flipped over y=x, this yields in-order access to
             // the src buffer.
             let mut dst = Channel::black(dst_size);
-            for x in 0..dst_size.x {
-                for y in 0..dst_size.y {
-                    let center = y as f32 * width;
-                    let lo = (center - width).floor() as isize;
-                    let hi = (center + width).ceil() as isize;
-
-                    let (px, filter) = (lo..hi).fold((0.0, 0.0), |acc, i| {
-                        let rel = i as f32 - center;
-                        let clipped = min(src.size().x - 1, max(0, i) as usize);
-                        let sample_index = x * src.size().x + clipped;
-                        let sample = src[sample_index];
-
-                        let filter = filter(rel, width);
-                        (acc.0 + sample * filter, acc.1 + filter)
-                    });
-
-                    let out_index = y * dst_size.x + x;
-                    dst[out_index] = px / filter;
+            for (dst_x, x) in lines.clone().enumerate() {
+                let line = x * stored;
+                for (dst_y, taps) in taps.iter().enumerate() {
+                    dst[dst_y * dst_size.x + dst_x] =
+                        taps.iter().map(|(i, weight)| src[line + i] * weight).sum();
                 }
             }
             dst
@@ -121,17 +378,42 @@ fn downscale_axis(src: &Image, dim: usize) -> Image {
         .collect::<Image>()
 }
 
-fn filter(src: f32, radius: f32) -> f32 {
-    let sawtooth = 1.0 - src.abs() / radius;
-    let x = if sawtooth < 0.0 {
-        0.0
-    } else if sawtooth > 1.0 {
-        1.0
-    } else {
-        sawtooth
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fitted(format: Format, src: Vec2U, pixel_aspect: f32, fill: bool) -> Layout {
+        Layout::fitted(format, src, pixel_aspect, fill)
+    }
+
+    #[test]
+    fn fit_and_fill_square_pixels() {
+        let format = Format::new(100, 100, 1.0);
+        let fit = fitted(format, Vec2U::new(200, 100), 1.0, false);
+        assert_eq!((fit.x.scale, fit.y.scale), (0.5, 0.5));
+        assert_eq!((fit.x.shift, fit.y.shift), (0.0, 25.0));
 
-    // Smoothstep
-    let rcp = 1.0 - x;
-    rcp * x * x + x * (1.0 - rcp * rcp)
+        let fill = fitted(format, Vec2U::new(200, 100), 1.0, true);
+        assert_eq!((fill.x.scale, fill.y.scale), (1.0, 1.0));
+        assert_eq!((fill.x.shift, fill.y.shift), (-50.0, 0.0));
+    }
+
+    // The frame keeps its shape as seen, whatever the pixels
+    #[test]
+    fn fit_keeps_shape_across_pixel_aspects() {
+        let cases = [
+            (Format::new(50, 100, 2.0), Vec2U::new(100, 100), 1.0),
+            (Format::new(720, 576, 1.0926), Vec2U::new(1920, 1080), 1.0),
+            (Format::new(1920, 1080, 1.0), Vec2U::new(1828, 1556), 2.0),
+        ];
+        for (format, src, pixel_aspect) in cases.iter() {
+            for fill in &[false, true] {
+                let layout = fitted(*format, *src, *pixel_aspect, *fill);
+                let before = src.x as f32 * pixel_aspect / src.y as f32;
+                let after = src.x as f32 * layout.x.scale * format.pixel_aspect
+                    / (src.y as f32 * layout.y.scale);
+                assert!((before - after).abs() < 1e-4, "{} {}", before, after);
+            }
+        }
+    }
 }
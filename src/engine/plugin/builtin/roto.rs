@@ -0,0 +1,68 @@
+use super::constant::frame_format;
+use crate::{
+    control,
+    image::{self, Channel, Image},
+    plugin::{self, *},
+    utils::{shape, Rect, Value, Vec2F},
+};
+use rayon::prelude::*;
+use std::path::Path;
+
+enum Parameters {
+    Width,
+    Height,
+    Shapes,
+    File,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("width", Value::Integer(0)),
+        control::Desc::new("height", Value::Integer(0)),
+        control::Desc::new("shapes", Value::Text("".into())),
+        control::Desc::file("file"),
+    ];
+    let desc = plugin::Desc::new("roto", &[], &controls);
+    Plugin::new(render, desc).format_dependent()
+}
+
+// Shapes from the file come first, followed by those in the
+// shapes control, each blending with the matte of those before
+// it. Every channel gets the matte.
+fn render(_: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let format = frame_format(
+        controls,
+        Parameters::Width as usize,
+        Parameters::Height as usize,
+        ctx,
+    );
+    let mut shapes = match controls[Parameters::File as usize].as_str() {
+        "" => Vec::new(),
+        file => shape::load(Path::new(file))?,
+    };
+    shapes.extend(shape::parse(
+        controls[Parameters::Shapes as usize].as_str(),
+    )?);
+
+    let frame = Rect::from_size(format.size());
+    let data = ctx.region.map_or(frame, |region| region.intersect(frame));
+    let mut channel = Channel::black(data.size());
+    channel.par_lines_mut().enumerate().for_each(|(y, line)| {
+        let y = (data.min.y + y as isize) as f32 + 0.5;
+        for (x, element) in line.iter_mut().enumerate() {
+            let pos = Vec2F::new((data.min.x + x as isize) as f32 + 0.5, y);
+            *element = shapes.iter().fold(0.0, |matte, shape| {
+                shape.blend.apply(matte, shape.coverage(pos))
+            });
+        }
+    });
+
+    let mut image = image::RGBA
+        .iter()
+        .map(|name| (name.to_string(), channel.clone()))
+        .collect::<Image>();
+    image.set_data_origin(data.min);
+    image.set_display_window(frame);
+    image.set_pixel_aspect(format.pixel_aspect);
+    Ok(image)
+}
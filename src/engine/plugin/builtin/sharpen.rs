@@ -0,0 +1,69 @@
+use super::blur::{blur_channel, filter};
+use crate::{
+    control,
+    image::Image,
+    plugin::{self, *},
+    utils::{Rect, Value, Vec2I},
+};
+use rayon::prelude::*;
+
+enum Parameters {
+    Amount,
+    Radius,
+    Threshold,
+}
+
+pub fn create() -> Plugin {
+    let controls = [
+        control::Desc::new("amount", Value::Real(0.5)),
+        control::Desc::new("radius", Value::Integer(2)),
+        control::Desc::new("threshold", Value::Real(0.0)),
+    ];
+    let desc = plugin::Desc::new("sharpen", &["bg"], &controls);
+    Plugin::new(render, desc).with_roi(roi)
+}
+
+fn roi(_: usize, region: Rect, controls: Controls) -> Option<Rect> {
+    let radius = controls[Parameters::Radius as usize].as_uint() as isize;
+    Some(region.grow(Vec2I::new(radius, radius)))
+}
+
+// Unsharp masking adds the amount times the difference between
+// each pixel and a blur of the given radius. Differences no
+// larger than the threshold are left alone so that flat areas
+// don't gain noise.
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => Ok(bg),
+        None => Err(String::from("Invalid background input")),
+    }?;
+
+    let amount = controls[Parameters::Amount as usize].as_real();
+    let radius = controls[Parameters::Radius as usize].as_uint();
+    let threshold = controls[Parameters::Threshold as usize].as_real();
+    if radius == 0 {
+        return Ok(bg.clone_region(ctx.region));
+    }
+
+    let filter = filter(radius);
+    Ok(bg.map_premultiplied(|bg| {
+        let region = bg.local_region(ctx.region);
+        bg.par_channels()
+            .map(|channel| {
+                let blurred = blur_channel(channel, &filter, channel.clip(region));
+                let mut out = channel.clone();
+                out.par_region_lines_mut(region)
+                    .zip(blurred.par_region_lines(region))
+                    .for_each(|(line, blurred)| {
+                        for (element, blurred) in line.iter_mut().zip(blurred.iter()) {
+                            let detail = *element - blurred;
+                            if detail.abs() > threshold {
+                                *element += amount * detail;
+                            }
+                        }
+                    });
+                out
+            })
+            .collect::<Image>()
+    }))
+}
@@ -5,45 +5,127 @@ use crate::{
     utils::Value,
 };
 use rayon::prelude::*;
+use std::borrow::Cow;
 
 enum Parameters {
-    R,
-    G,
-    B,
-    A,
+    Mapping,
+    Keep,
+}
+
+enum Source<'a> {
+    Channel(&'a Channel),
+    Constant(f32),
 }
 
 pub fn create() -> Plugin {
     let controls = [
-        control::Desc::new("r", Value::Integer(0)),
-        control::Desc::new("g", Value::Integer(1)),
-        control::Desc::new("b", Value::Integer(2)),
-        control::Desc::new("a", Value::Integer(3)),
+        control::Desc::new(
+            "mapping",
+            Value::Text(
+                "rgba.red=bg.rgba.red rgba.green=bg.rgba.green \
+                 rgba.blue=bg.rgba.blue rgba.alpha=bg.rgba.alpha"
+                    .into(),
+            ),
+        ),
+        control::Desc::new("keep", Value::Boolean(false)),
     ];
-    let desc = plugin::Desc::new("shuffle", &["bg"], &controls);
+    let desc = plugin::Desc::new("shuffle", &["bg", "fg"], &controls);
     Plugin::new(render, desc)
 }
 
-fn render(inputs: Inputs, controls: Controls) -> Result<Image, String> {
+// The mapping is a list of output=source pairs separated by
+// spaces or commas, such as "rgba.alpha=fg.rgba.red depth.Z=0".
+// Sources name a channel of either input or give a constant.
+// With keep set, background channels that aren't replaced
+// pass through ahead of the mapped ones.
+fn render(inputs: Inputs, controls: Controls, ctx: &Context) -> Result<Image, String> {
     let bg = match inputs[0] {
         Some(bg) => Ok(bg),
         None => Err("Invalid background input".to_string()),
     }?;
+    // Only the requested part is shuffled
+    let bg = &bg.clone_region(ctx.region);
 
-    let remap = [
-        controls[Parameters::R as usize].as_uint(),
-        controls[Parameters::G as usize].as_uint(),
-        controls[Parameters::B as usize].as_uint(),
-        controls[Parameters::A as usize].as_uint(),
-    ];
+    // Foreground pixels line up with the background data window
+    let fg = inputs[1].map(|fg| {
+        if fg.data_window() == bg.data_window() {
+            Cow::Borrowed(fg)
+        } else {
+            Cow::Owned(fg.reframed(bg.data_window()))
+        }
+    });
+    let fg = fg.as_deref();
 
-    Ok(remap
-        .par_iter()
-        .map(|remap| {
-            bg.channels()
-                .nth(*remap)
-                .cloned()
-                .unwrap_or_else(|| Channel::black(bg.desc().size))
+    let mapping = controls[Parameters::Mapping as usize].as_str();
+    let keep = controls[Parameters::Keep as usize].as_bool();
+
+    let pairs = mapping
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(dst), Some(src)) if !dst.is_empty() => {
+                    Ok((dst.to_string(), source(src, bg, fg)?))
+                }
+                _ => Err(format!("Invalid shuffle mapping: {}", pair)),
+            }
         })
-        .collect::<Image>())
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let size = bg.size();
+    let shuffled = pairs
+        .into_par_iter()
+        .map(|(name, src)| {
+            let channel = match src {
+                Source::Channel(channel) => channel.clone(),
+                Source::Constant(value) => Channel::filled(size, value),
+            };
+            (name, channel)
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = if keep {
+        let mut out = bg.clone();
+        for (name, channel) in shuffled {
+            out.insert_channel(name, channel);
+        }
+        out
+    } else {
+        // Later entries for the same output win
+        let mut unique: Vec<(String, Channel)> = Vec::new();
+        for (name, channel) in shuffled {
+            match unique.iter_mut().find(|(existing, _)| *existing == name) {
+                Some(entry) => entry.1 = channel,
+                None => unique.push((name, channel)),
+            }
+        }
+        if unique.is_empty() {
+            return Err("Shuffle mapping has no outputs".to_string());
+        }
+        let mut out = unique.into_iter().collect::<Image>();
+        out.set_data_origin(bg.data_window().min);
+        out.set_display_window(bg.display_window());
+        out.set_pixel_aspect(bg.pixel_aspect());
+        out
+    };
+    out.set_premultiplied(bg.is_premultiplied());
+    Ok(out)
+}
+
+fn source<'a>(src: &str, bg: &'a Image, fg: Option<&'a Image>) -> Result<Source<'a>, String> {
+    if let Ok(value) = src.parse::<f32>() {
+        return Ok(Source::Constant(value));
+    }
+    let mut parts = src.splitn(2, '.');
+    let image = match parts.next() {
+        Some("bg") => bg,
+        Some("fg") => fg.ok_or("Invalid foreground input")?,
+        _ => return Err(format!("Unrecognized shuffle source: {}", src)),
+    };
+    let name = parts.next().unwrap_or_default();
+    image
+        .channel_by_name(name)
+        .map(Source::Channel)
+        .ok_or_else(|| format!("Channel not found: {}", src))
 }
@@ -0,0 +1,20 @@
+use crate::{
+    image::Image,
+    plugin::{self, *},
+};
+
+pub fn create() -> Plugin {
+    let desc = plugin::Desc::new("unpremult", &["bg"], &[]);
+    Plugin::new(render, desc)
+}
+
+fn render(inputs: Inputs, _: Controls, ctx: &Context) -> Result<Image, String> {
+    let bg = match inputs[0] {
+        Some(bg) => bg,
+        None => return Err(String::from("Invalid background input")),
+    };
+
+    let mut out = bg.clone_region(ctx.region);
+    out.unpremultiply();
+    Ok(out)
+}
@@ -1,5 +1,5 @@
 use super::{builtin, Plugin};
-use crate::{utils::Id};
+use crate::utils::Id;
 use std::collections::HashMap;
 
 type Create = fn() -> Plugin;
@@ -11,7 +11,6 @@ struct Context<'a> {
 
 #[allow(clippy::implicit_hasher)]
 pub fn populate_builtin(plugins: &mut HashMap<Id, Plugin>, names: &mut HashMap<String, Id>) {
-    let mut id = 0;
     let creates = [
         builtin::load::create,
         builtin::merge::create,
@@ -20,11 +19,34 @@ pub fn populate_builtin(plugins: &mut HashMap<Id, Plugin>, names: &mut HashMap<S
         builtin::adjust::create,
         builtin::resize::create,
         builtin::crop::create,
+        builtin::convolve::create,
+        builtin::defocus::create,
+        builtin::colorspace::create,
+        builtin::lut::create,
+        builtin::grade::create,
+        builtin::premult::create,
+        builtin::unpremult::create,
+        builtin::constant::create,
+        builtin::ramp::create,
+        builtin::checkerboard::create,
+        builtin::colorbars::create,
+        builtin::noise::create,
+        builtin::roto::create,
+        builtin::keyer::create,
+        builtin::luma_key::create,
+        builtin::difference_key::create,
+        builtin::channel_key::create,
+        builtin::erode::create,
+        builtin::dilate::create,
+        builtin::edge_detect::create,
+        builtin::matte_choke::create,
+        builtin::sharpen::create,
+        builtin::reformat::create,
+        builtin::gradient::create,
     ];
     let mut ctx = Context { plugins, names };
-    for create in creates.iter() {
+    for (id, create) in creates.iter().enumerate() {
         add_plugin(&mut ctx, *create, id);
-        id += 1;
     }
 }
 
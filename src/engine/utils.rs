@@ -1,7 +1,16 @@
 mod vector2;
 pub use vector2::*;
 
+mod rect;
+pub use rect::Rect;
+
+pub mod distance;
+pub mod fft;
+pub mod hash;
 pub mod io;
+pub mod morphology;
+pub mod noise;
+pub mod shape;
 
 mod enumeration;
 pub use enumeration::Enumeration;
@@ -0,0 +1,88 @@
+use super::Vec2U;
+use rayon::prelude::*;
+
+// Squared euclidean distances from every element of a grid to the
+// nearest seed, or infinity with no seeds. Runs in linear time by
+// transforming the columns and then the rows, as described by
+// Felzenszwalb and Huttenlocher.
+pub fn squared<F>(size: Vec2U, seed: F) -> Vec<f32>
+where
+    F: Fn(usize) -> bool + Sync,
+{
+    let columns = (0..size.x)
+        .into_par_iter()
+        .map(|x| {
+            let column = (0..size.y)
+                .map(|y| {
+                    if seed(y * size.x + x) {
+                        0.0
+                    } else {
+                        f32::INFINITY
+                    }
+                })
+                .collect::<Vec<_>>();
+            transform(&column)
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = vec![0.0; size.x * size.y];
+    if size.x == 0 {
+        return out;
+    }
+    out.par_chunks_mut(size.x)
+        .enumerate()
+        .for_each(|(y, line)| {
+            let row = columns.iter().map(|column| column[y]).collect::<Vec<_>>();
+            line.copy_from_slice(&transform(&row));
+        });
+    out
+}
+
+// The lower envelope of parabolas rooted at each element
+fn transform(f: &[f32]) -> Vec<f32> {
+    let n = f.len();
+    let mut out = vec![f32::INFINITY; n];
+    // Roots of the parabolas in the envelope
+    // and where each one takes over
+    let mut roots = Vec::with_capacity(n);
+    let mut bounds = Vec::with_capacity(n + 1);
+    for q in 0..n {
+        if f[q].is_infinite() {
+            continue;
+        }
+        let value = f[q] + (q * q) as f32;
+        loop {
+            let start = match roots.last() {
+                Some(&root) => {
+                    let root: usize = root;
+                    let intersection = (value - (f[root] + (root * root) as f32))
+                        / (2.0 * (q as f32 - root as f32));
+                    if intersection <= *bounds.last().unwrap_or(&f32::NEG_INFINITY) {
+                        roots.pop();
+                        bounds.pop();
+                        continue;
+                    }
+                    intersection
+                }
+                None => f32::NEG_INFINITY,
+            };
+            roots.push(q);
+            bounds.push(start);
+            break;
+        }
+    }
+    if roots.is_empty() {
+        return out;
+    }
+
+    let mut k = 0;
+    for (q, out) in out.iter_mut().enumerate() {
+        while k + 1 < roots.len() && bounds[k + 1] < q as f32 {
+            k += 1;
+        }
+        let root = roots[k];
+        let d = q as f32 - root as f32;
+        *out = d * d + f[root];
+    }
+    out
+}
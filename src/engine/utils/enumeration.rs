@@ -21,6 +21,13 @@ impl Enumeration {
         self.values.get(key).copied()
     }
 
+    pub fn name(&self, index: usize) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(_, i)| **i == index)
+            .map(|(name, _)| name.as_str())
+    }
+
     pub fn len(&self) -> usize {
         self.values.len()
     }
@@ -0,0 +1,113 @@
+use super::Vec2U;
+use rayon::prelude::*;
+use std::{
+    f32::consts::PI,
+    ops::{Add, Mul, Sub},
+};
+
+#[derive(PartialEq, Default, Copy, Clone, Debug)]
+pub struct Complex {
+    pub re: f32,
+    pub im: f32,
+}
+
+impl Complex {
+    pub fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+
+    pub fn from_angle(angle: f32) -> Self {
+        Self::new(angle.cos(), angle.sin())
+    }
+}
+
+impl Add<Self> for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub<Self> for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul<Self> for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+// In-place iterative radix-2 transform.
+// The length of data must be a power of two.
+pub fn fft(data: &mut [Complex], inverse: bool) {
+    let len = data.len();
+    assert!(len.is_power_of_two());
+    if len < 2 {
+        return;
+    }
+
+    let shift = usize::BITS - len.trailing_zeros();
+    for i in 0..len {
+        let j = i.reverse_bits() >> shift;
+        if j > i {
+            data.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut width = 2;
+    while width <= len {
+        let half = width / 2;
+        // Computing each twiddle directly rather than by repeated
+        // multiplication keeps error from accumulating on long rows
+        let twiddles = (0..half)
+            .map(|i| Complex::from_angle(sign * 2.0 * PI * i as f32 / width as f32))
+            .collect::<Vec<_>>();
+        for chunk in data.chunks_exact_mut(width) {
+            let (lo, hi) = chunk.split_at_mut(half);
+            for ((a, b), w) in lo.iter_mut().zip(hi.iter_mut()).zip(twiddles.iter()) {
+                let t = *b * *w;
+                *b = *a - t;
+                *a = *a + t;
+            }
+        }
+        width *= 2;
+    }
+
+    if inverse {
+        let scale = (len as f32).recip();
+        for element in data.iter_mut() {
+            element.re *= scale;
+            element.im *= scale;
+        }
+    }
+}
+
+// Both dimensions of size must be powers of two
+pub fn fft2d(data: &mut [Complex], size: Vec2U, inverse: bool) {
+    assert!(size.area() == data.len());
+    data.par_chunks_mut(size.x)
+        .for_each(|row| fft(row, inverse));
+    let mut columns = transpose(data, size);
+    columns
+        .par_chunks_mut(size.y)
+        .for_each(|column| fft(column, inverse));
+    data.copy_from_slice(&transpose(&columns, Vec2U::new(size.y, size.x)));
+}
+
+fn transpose(data: &[Complex], size: Vec2U) -> Vec<Complex> {
+    (0..size.x)
+        .flat_map(|x| (0..size.y).map(move |y| data[y * size.x + x]))
+        .collect()
+}
@@ -1,4 +1,4 @@
-use crate::image::Image;
+use crate::{image::Image, utils::Rect};
 use std::{fs::File, path::Path};
 
 mod png;
@@ -6,12 +6,17 @@ mod png;
 const EXT_ERR: &str = "File extension not recognized";
 
 pub fn load(path: &Path) -> Result<Image, String> {
+    load_region(path, None)
+}
+
+// Reads only as much of the file into memory as the region covers
+pub fn load_region(path: &Path, region: Option<Rect>) -> Result<Image, String> {
     match path.extension() {
         Some(ext) => match ext.to_str() {
             Some(ext) => {
                 let file = File::open(path).map_err(|e| format!("{}", e))?;
                 match ext {
-                    "png" => png::load(&file),
+                    "png" => png::load(&file, region),
                     _ => Err(EXT_ERR.to_string()),
                 }
             }
@@ -36,3 +41,39 @@ pub fn save(path: &Path, image: &Image) -> Result<(), String> {
         None => Err(EXT_ERR.to_string()),
     }
 }
+
+// Output that is written a few rows at a time
+pub enum Stream {
+    Png(png::Stream),
+}
+
+impl Stream {
+    pub fn write(&mut self, image: &Image) -> Result<(), String> {
+        match self {
+            Stream::Png(stream) => stream.write(image),
+        }
+    }
+
+    pub fn finish(self) -> Result<(), String> {
+        match self {
+            Stream::Png(stream) => stream.finish(),
+        }
+    }
+}
+
+// The pixel aspect is written ahead of the rows
+pub fn stream(path: &Path, frame: Rect, pixel_aspect: f32) -> Result<Stream, String> {
+    match path.extension() {
+        Some(ext) => match ext.to_str() {
+            Some(ext) => {
+                let file = File::create(path).map_err(|e| format!("{}", e))?;
+                match ext {
+                    "png" => png::Stream::new(&file, frame, pixel_aspect).map(Stream::Png),
+                    _ => Err(EXT_ERR.to_string()),
+                }
+            }
+            None => Err(EXT_ERR.to_string()),
+        },
+        None => Err(EXT_ERR.to_string()),
+    }
+}
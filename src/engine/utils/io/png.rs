@@ -1,15 +1,25 @@
 use crate::{
-    image::{self, Image},
-    utils::{Vec2I, Vec2U},
+    image::{self, Channel, Image},
+    utils::{Rect, Vec2I, Vec2U},
 };
-use ::png::{BitDepth, ColorType, Decoder, OutputInfo};
+use ::png::{chunk, BitDepth, ColorType, Decoder, Encoder, PixelDimensions, Writer};
+use deflate::{write::ZlibEncoder, Compression};
 use std::{
     fs::File,
-    io::{self, BufWriter},
+    io::{self, BufWriter, Write},
+    mem,
 };
 
-pub fn load(file: &File) -> Result<Image, String> {
-    let (img_data, info) = extract(file).map_err(|e| format!("{}", e))?;
+// Bytes of compressed data to gather before writing a chunk
+const CHUNK_SIZE: usize = 1 << 16;
+
+// Only keeps the rows and columns inside the region, so large
+// files can be read a piece at a time. The display window
+// always covers the whole file. Pixel dimensions give the
+// pixel aspect, which is otherwise square.
+pub fn load(file: &File, region: Option<Rect>) -> Result<Image, String> {
+    let decoder = Decoder::new(file);
+    let (info, mut reader) = decoder.read_info().map_err(|e| format!("{}", e))?;
     let channel_count = match info.color_type {
         ColorType::Grayscale => 1,
         ColorType::GrayscaleAlpha => 2,
@@ -22,59 +32,195 @@ pub fn load(file: &File) -> Result<Image, String> {
     };
 
     let size = Vec2U::new(info.width as usize, info.height as usize);
-    let desc = image::Desc::new(size, channel_count);
-    let mut image = Image::from_desc(desc);
-    for y in 0..size.y {
-        for x in 0..size.x {
-            let start = (y * size.x + x) * channel_count;
-            let end = start + channel_count;
-            let pixel = &img_data[start..end];
-            let pos: Vec2I = Vec2U::new(x, y).into();
-            let dst_index = image[0].index_of(pos).unwrap();
-            for (i, channel) in pixel.iter().enumerate() {
-                image[i][dst_index] = *channel as f32 / 255.0;
+    let frame = Rect::from_size(size);
+    let data = region.map_or(frame, |region| region.intersect(frame));
+    let (rows, columns) = (data.rows(), data.columns());
+    let mut channels = vec![Channel::black(data.size()); channel_count];
+
+    let interlaced = reader.info().interlaced;
+    let aspect = reader.info().pixel_dims.as_ref().and_then(pixel_aspect);
+    let mut store = |y: usize, line: &[u8]| {
+        if !rows.contains(&y) {
+            return;
+        }
+        let line = &line[columns.start * channel_count..columns.end * channel_count];
+        for (x, pixel) in line.chunks_exact(channel_count).enumerate() {
+            let pos = Vec2U::new(x, y - rows.start).into();
+            for (channel, element) in channels.iter_mut().zip(pixel.iter()) {
+                let index = channel.index_of(pos).unwrap();
+                channel[index] = *element as f32 / 255.0;
+            }
+        }
+    };
+    if interlaced {
+        // Rows arrive out of order, so decode the whole frame
+        let mut buffer = vec![0; info.buffer_size()];
+        reader
+            .next_frame(&mut buffer)
+            .map_err(|e| format!("{}", e))?;
+        for (y, line) in buffer.chunks_exact(info.line_size).enumerate() {
+            store(y, line);
+        }
+    } else {
+        // Stop once the last needed row is read
+        let last = if data.is_empty() { 0 } else { rows.end };
+        for y in 0..last {
+            match reader.next_row().map_err(|e| format!("{}", e))? {
+                Some(line) => store(y, line),
+                None => return Err("PNG ended early".to_string()),
             }
         }
     }
+
+    let names = image::default_names(channel_count);
+    let mut image = names.into_iter().zip(channels).collect::<Image>();
+    image.set_data_origin(data.min);
+    image.set_display_window(frame);
+    if let Some(aspect) = aspect {
+        image.set_pixel_aspect(aspect);
+    }
     Ok(image)
 }
 
-fn extract(file: &File) -> io::Result<(Vec<u8>, OutputInfo)> {
-    let decoder = Decoder::new(file);
-    let (info, mut reader) = decoder.read_info()?;
-    let mut img_data = vec![0; info.buffer_size()];
-    reader.next_frame(&mut img_data)?;
-    Ok((img_data, info))
+pub fn save(file: &File, image: &Image) -> Result<(), String> {
+    let frame = image.display_window();
+    let mut stream = Stream::new(file, frame, image.pixel_aspect())?;
+    stream.write(image)?;
+    stream.finish()
 }
 
-pub fn save(file: &File, image: &Image) -> Result<(), String> {
-    let size = image.desc().size;
-    let x = size.x as u32;
-    let y = size.y as u32;
-
-    let writer = &mut BufWriter::new(file);
-    let mut encoder = png::Encoder::new(writer, x, y);
-    encoder.set_color(ColorType::RGBA);
-    encoder.set_depth(BitDepth::Eight);
-    let mut writer = encoder.write_header().map_err(|e| format!("{}", e))?;
-
-    let element_count = size.area() * image.channel_count();
-    let mut data = vec![0; element_count];
-    for (channel_i, channel) in image.channels().enumerate() {
-        for (element_i, element) in channel.elements().enumerate() {
-            let i = element_i * image.channel_count() + channel_i;
-            let clamped = if *element < 0.0 {
-                0.0
-            } else if *element > 1.0 {
-                1.0
-            } else {
-                *element
-            };
-            data[i] = (clamped * 255.0) as u8;
+// Writes an image as rows of it are rendered, top to bottom
+pub struct Stream {
+    encoder: ZlibEncoder<Chunks>,
+    frame: Rect,
+    next_row: isize,
+}
+
+impl Stream {
+    pub fn new(file: &File, frame: Rect, pixel_aspect: f32) -> Result<Self, String> {
+        let size = frame.size();
+        let writer = BufWriter::new(file.try_clone().map_err(|e| format!("{}", e))?);
+        let mut encoder = Encoder::new(writer, size.x as u32, size.y as u32);
+        encoder.set_color(ColorType::RGBA);
+        encoder.set_depth(BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|e| format!("{}", e))?;
+        // Square pixels are left implicit
+        if pixel_aspect != 1.0 {
+            writer
+                .write_chunk(chunk::pHYs, &pixel_dims(pixel_aspect))
+                .map_err(|e| format!("{}", e))?;
         }
+        let chunks = Chunks {
+            writer,
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+        };
+        Ok(Self {
+            encoder: ZlibEncoder::new(chunks, Compression::Default),
+            frame,
+            next_row: frame.min.y,
+        })
     }
 
-    writer
-        .write_image_data(data.as_slice())
-        .map_err(|e| format!("{}", e))
+    // Writes the rows of the frame the image covers that follow those
+    // already written. Any gap in between is written as transparent.
+    pub fn write(&mut self, image: &Image) -> Result<(), String> {
+        let end = image.data_window().max.y.min(self.frame.max.y);
+        if end <= self.next_row {
+            return Ok(());
+        }
+        let rows = Rect::new(
+            Vec2I::new(self.frame.min.x, self.next_row),
+            Vec2I::new(self.frame.max.x, end),
+        );
+        // PNG stores straight alpha
+        let mut image = image.reframed(rows);
+        image.unpremultiply();
+
+        // Gray images are written to all three colors
+        // and images without alpha are opaque
+        let red = image.channel_by_name(image::RED);
+        let sources = [
+            (red, 0.0),
+            (image.channel_by_name(image::GREEN).or(red), 0.0),
+            (image.channel_by_name(image::BLUE).or(red), 0.0),
+            (image.channel_by_name(image::ALPHA), 1.0),
+        ];
+        let width = rows.size().x;
+        let mut line = vec![0; 1 + width * sources.len()];
+        for y in 0..rows.size().y {
+            // Rows are stored unfiltered
+            line[0] = 0;
+            for (channel_i, (channel, fallback)) in sources.iter().enumerate() {
+                for x in 0..width {
+                    let element = channel.map_or(*fallback, |channel| channel[y * width + x]);
+                    let i = 1 + x * sources.len() + channel_i;
+                    line[i] = (element.clamp(0.0, 1.0) * 255.0) as u8;
+                }
+            }
+            self.encoder
+                .write_all(&line)
+                .map_err(|e| format!("{}", e))?;
+        }
+        self.next_row = end;
+        Ok(())
+    }
+
+    // Rows that were never written are left transparent
+    pub fn finish(mut self) -> Result<(), String> {
+        let line = vec![0; 1 + self.frame.size().x * 4];
+        for _ in self.next_row..self.frame.max.y {
+            self.encoder
+                .write_all(&line)
+                .map_err(|e| format!("{}", e))?;
+        }
+        let mut chunks = self.encoder.finish().map_err(|e| format!("{}", e))?;
+        chunks.flush().map_err(|e| format!("{}", e))
+    }
+}
+
+// Pixels per unit run the other way to their size
+fn pixel_aspect(dims: &PixelDimensions) -> Option<f32> {
+    if dims.xppu == 0 || dims.yppu == 0 {
+        None
+    } else {
+        Some(dims.yppu as f32 / dims.xppu as f32)
+    }
+}
+
+// The body of a pHYs chunk with no unit, which
+// only records the shape of the pixels
+fn pixel_dims(pixel_aspect: f32) -> Vec<u8> {
+    let xppu = 1000u32;
+    let yppu = (xppu as f32 * pixel_aspect).round().max(1.0) as u32;
+    let mut data = Vec::with_capacity(9);
+    data.extend_from_slice(&xppu.to_be_bytes());
+    data.extend_from_slice(&yppu.to_be_bytes());
+    data.push(0);
+    data
+}
+
+// Gathers compressed image data into PNG chunks
+struct Chunks {
+    writer: Writer<BufWriter<File>>,
+    buffer: Vec<u8>,
+}
+
+impl Write for Chunks {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.buffer.is_empty() {
+            let data = mem::take(&mut self.buffer);
+            self.writer
+                .write_chunk(chunk::IDAT, &data)
+                .map_err(|e| io::Error::other(format!("{}", e)))?;
+        }
+        Ok(())
+    }
 }
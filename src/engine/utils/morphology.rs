@@ -0,0 +1,146 @@
+use super::Vec2U;
+use rayon::prelude::*;
+
+// Combines each element of a grid with those within a disk of the
+// radius around it, such as taking their maximum to dilate or their
+// minimum to erode. Elements past the edges count as zero. The disk
+// is made of rows of differing widths, and each width is applied
+// to every row up front, so the cost grows with the radius rather
+// than the area of the disk.
+pub fn disk<F>(values: &[f32], size: Vec2U, radius: usize, op: F) -> Vec<f32>
+where
+    F: Fn(f32, f32) -> f32 + Sync,
+{
+    if radius == 0 || size.x == 0 {
+        return values.to_vec();
+    }
+    let radius = radius as isize;
+    // Half widths of the rows of the disk, rounded out
+    // so that small disks don't come out as crosses
+    let widths = (-radius..=radius)
+        .map(|dy| ((radius * (radius + 1) - dy * dy) as f32).sqrt() as usize)
+        .collect::<Vec<_>>();
+    let mut unique = widths.clone();
+    unique.sort_unstable();
+    unique.dedup();
+    let spans = unique
+        .iter()
+        .map(|width| {
+            let mut out = vec![0.0; values.len()];
+            out.par_chunks_mut(size.x)
+                .zip(values.par_chunks(size.x))
+                .for_each(|(out, line)| window(line, out, *width, &op));
+            out
+        })
+        .collect::<Vec<_>>();
+
+    let mut out = vec![0.0; values.len()];
+    out.par_chunks_mut(size.x)
+        .enumerate()
+        .for_each(|(y, line)| {
+            for (dy, width) in (-radius..=radius).zip(widths.iter()) {
+                let span = &spans[unique.binary_search(width).unwrap()];
+                let row = y as isize + dy;
+                let first = dy == -radius;
+                if row < 0 || row >= size.y as isize {
+                    for element in line.iter_mut() {
+                        *element = if first { 0.0 } else { op(*element, 0.0) };
+                    }
+                    continue;
+                }
+                let start = row as usize * size.x;
+                let source = &span[start..start + size.x];
+                for (element, value) in line.iter_mut().zip(source) {
+                    *element = if first { *value } else { op(*element, *value) };
+                }
+            }
+        });
+    out
+}
+
+// Combines every run of elements reaching the half width to either
+// side, in constant time per element by splitting the line into
+// blocks the length of a run and combining forward and backward
+// within each, as described by van Herk and by Gil and Werman
+fn window<F>(line: &[f32], out: &mut [f32], width: usize, op: &F)
+where
+    F: Fn(f32, f32) -> f32,
+{
+    if width == 0 {
+        out.copy_from_slice(line);
+        return;
+    }
+    let run = 2 * width + 1;
+    let mut padded = vec![0.0; width];
+    padded.extend_from_slice(line);
+    padded.resize(line.len() + 2 * width, 0.0);
+
+    let mut forward = padded.clone();
+    let mut backward = padded.clone();
+    for i in 1..padded.len() {
+        if i % run != 0 {
+            forward[i] = op(forward[i - 1], padded[i]);
+        }
+    }
+    for i in (0..padded.len() - 1).rev() {
+        if (i + 1) % run != 0 {
+            backward[i] = op(backward[i + 1], padded[i]);
+        }
+    }
+    for (x, out) in out.iter_mut().enumerate() {
+        *out = op(backward[x], forward[x + 2 * width]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Visits every element within the disk directly
+    fn brute<F: Fn(f32, f32) -> f32>(
+        values: &[f32],
+        size: Vec2U,
+        radius: isize,
+        op: F,
+    ) -> Vec<f32> {
+        let value = |x: isize, y: isize| {
+            if x < 0 || y < 0 || x >= size.x as isize || y >= size.y as isize {
+                0.0
+            } else {
+                values[y as usize * size.x + x as usize]
+            }
+        };
+        let mut out = Vec::with_capacity(values.len());
+        for y in 0..size.y as isize {
+            for x in 0..size.x as isize {
+                let mut acc = value(x, y);
+                for dy in -radius..=radius {
+                    let width = ((radius * (radius + 1) - dy * dy) as f32).sqrt() as isize;
+                    for dx in -width..=width {
+                        acc = op(acc, value(x + dx, y + dy));
+                    }
+                }
+                out.push(acc);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let size = Vec2U::new(13, 9);
+        let values = (0..size.x * size.y)
+            .map(|i| (i * 37 % 23) as f32 / 23.0)
+            .collect::<Vec<_>>();
+        for radius in 0..6 {
+            for op in &[f32::min, f32::max] {
+                assert_eq!(
+                    disk(&values, size, radius, op),
+                    brute(&values, size, radius as isize, op),
+                    "radius {}",
+                    radius
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+// Coherent noise in three dimensions, so that two can span
+// the image and the third can move through time. Each kind
+// gives values from about 0 to 1.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Kind {
+    Perlin,
+    Simplex,
+    Worley,
+}
+
+impl Kind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "perlin" => Some(Kind::Perlin),
+            "simplex" => Some(Kind::Simplex),
+            "worley" => Some(Kind::Worley),
+            _ => None,
+        }
+    }
+
+    pub fn sample(self, p: [f32; 3], seed: u32) -> f32 {
+        match self {
+            Kind::Perlin => perlin(p, seed) * 0.5 + 0.5,
+            Kind::Simplex => simplex(p, seed) * 0.5 + 0.5,
+            Kind::Worley => worley(p, seed),
+        }
+    }
+}
+
+// Sums octaves of noise, each at the lacunarity times the frequency
+// and the gain times the amplitude of the one before. The sum is
+// normalized by the total amplitude to stay in the same range.
+pub fn fractal(
+    kind: Kind,
+    p: [f32; 3],
+    octaves: usize,
+    lacunarity: f32,
+    gain: f32,
+    seed: u32,
+) -> f32 {
+    let mut sum = 0.0;
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    for octave in 0..octaves as u32 {
+        let p = [p[0] * frequency, p[1] * frequency, p[2] * frequency];
+        sum += kind.sample(p, seed.wrapping_add(octave)) * amplitude;
+        total += amplitude;
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+    if total > 0.0 {
+        sum / total
+    } else {
+        0.0
+    }
+}
+
+// Gradient noise from -1 to 1 on a cubic lattice
+pub fn perlin(p: [f32; 3], seed: u32) -> f32 {
+    let cell = [p[0].floor(), p[1].floor(), p[2].floor()];
+    let f = [p[0] - cell[0], p[1] - cell[1], p[2] - cell[2]];
+    let i = [cell[0] as i32, cell[1] as i32, cell[2] as i32];
+    let u = [fade(f[0]), fade(f[1]), fade(f[2])];
+
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let h = hash([i[0] + dx, i[1] + dy, i[2] + dz], seed);
+        gradient(h, [f[0] - dx as f32, f[1] - dy as f32, f[2] - dz as f32])
+    };
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), u[0]);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), u[0]);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), u[0]);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), u[0]);
+    let y0 = lerp(x00, x10, u[1]);
+    let y1 = lerp(x01, x11, u[1]);
+    lerp(y0, y1, u[2])
+}
+
+// Gradient noise from about -1 to 1 on a lattice of tetrahedra,
+// which avoids the axis aligned look of Perlin noise
+pub fn simplex(p: [f32; 3], seed: u32) -> f32 {
+    const SKEW: f32 = 1.0 / 3.0;
+    const UNSKEW: f32 = 1.0 / 6.0;
+
+    let s = (p[0] + p[1] + p[2]) * SKEW;
+    let i = [
+        (p[0] + s).floor() as i32,
+        (p[1] + s).floor() as i32,
+        (p[2] + s).floor() as i32,
+    ];
+    let t = (i[0] + i[1] + i[2]) as f32 * UNSKEW;
+    let x0 = [
+        p[0] - (i[0] as f32 - t),
+        p[1] - (i[1] as f32 - t),
+        p[2] - (i[2] as f32 - t),
+    ];
+
+    // Which of the six tetrahedra in the cube holds the point
+    let (first, second) = if x0[0] >= x0[1] {
+        if x0[1] >= x0[2] {
+            ([1, 0, 0], [1, 1, 0])
+        } else if x0[0] >= x0[2] {
+            ([1, 0, 0], [1, 0, 1])
+        } else {
+            ([0, 0, 1], [1, 0, 1])
+        }
+    } else if x0[1] < x0[2] {
+        ([0, 0, 1], [0, 1, 1])
+    } else if x0[0] < x0[2] {
+        ([0, 1, 0], [0, 1, 1])
+    } else {
+        ([0, 1, 0], [1, 1, 0])
+    };
+
+    let corners = [[0, 0, 0], first, second, [1, 1, 1]];
+    corners
+        .iter()
+        .enumerate()
+        .map(|(n, offset)| {
+            let d = [
+                x0[0] - offset[0] as f32 + n as f32 * UNSKEW,
+                x0[1] - offset[1] as f32 + n as f32 * UNSKEW,
+                x0[2] - offset[2] as f32 + n as f32 * UNSKEW,
+            ];
+            let falloff = 0.6 - d[0] * d[0] - d[1] * d[1] - d[2] * d[2];
+            if falloff <= 0.0 {
+                return 0.0;
+            }
+            let h = hash([i[0] + offset[0], i[1] + offset[1], i[2] + offset[2]], seed);
+            falloff.powi(4) * gradient(h, d)
+        })
+        .sum::<f32>()
+        * 32.0
+}
+
+// Distance to the nearest of points scattered one per cell,
+// giving a cellular pattern from 0 at the points to about 1
+pub fn worley(p: [f32; 3], seed: u32) -> f32 {
+    let cell = [
+        p[0].floor() as i32,
+        p[1].floor() as i32,
+        p[2].floor() as i32,
+    ];
+    let mut nearest = f32::MAX;
+    for dz in -1..=1 {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let neighbor = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                let h = hash(neighbor, seed);
+                let point = [
+                    neighbor[0] as f32 + unit(h),
+                    neighbor[1] as f32 + unit(mix(h ^ 0x68e3_1da4)),
+                    neighbor[2] as f32 + unit(mix(h ^ 0xb529_7a4d)),
+                ];
+                let d = [p[0] - point[0], p[1] - point[1], p[2] - point[2]];
+                nearest = nearest.min(d[0] * d[0] + d[1] * d[1] + d[2] * d[2]);
+            }
+        }
+    }
+    nearest.sqrt()
+}
+
+// Dots the offset with one of the twelve
+// directions to the edges of a cube
+fn gradient(hash: u32, d: [f32; 3]) -> f32 {
+    match hash % 12 {
+        0 => d[0] + d[1],
+        1 => -d[0] + d[1],
+        2 => d[0] - d[1],
+        3 => -d[0] - d[1],
+        4 => d[0] + d[2],
+        5 => -d[0] + d[2],
+        6 => d[0] - d[2],
+        7 => -d[0] - d[2],
+        8 => d[1] + d[2],
+        9 => -d[1] + d[2],
+        10 => d[1] - d[2],
+        _ => -d[1] - d[2],
+    }
+}
+
+fn hash(cell: [i32; 3], seed: u32) -> u32 {
+    let mut h = seed.wrapping_mul(0x9e37_79b9);
+    for coordinate in cell.iter() {
+        h = mix(h ^ (*coordinate as u32).wrapping_mul(0x85eb_ca6b));
+    }
+    h
+}
+
+// The finalizer from MurmurHash3
+fn mix(mut h: u32) -> u32 {
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    h
+}
+
+// Maps a hash to [0, 1)
+fn unit(h: u32) -> f32 {
+    (h >> 8) as f32 / (1 << 24) as f32
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
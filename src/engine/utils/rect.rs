@@ -0,0 +1,67 @@
+use super::{Vec2I, Vec2U};
+use std::{cmp::max, ops::Range};
+
+// A block of pixels including its minimum
+// corner and excluding its maximum corner
+#[derive(PartialEq, Eq, Default, Copy, Clone, Debug)]
+pub struct Rect {
+    pub min: Vec2I,
+    pub max: Vec2I,
+}
+
+impl Rect {
+    pub fn new(min: Vec2I, max: Vec2I) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_size(size: Vec2U) -> Self {
+        Self::new(Vec2I::default(), size.into())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max.x <= self.min.x || self.max.y <= self.min.y
+    }
+
+    pub fn size(&self) -> Vec2U {
+        Vec2U::new(
+            max(0, self.max.x - self.min.x) as usize,
+            max(0, self.max.y - self.min.y) as usize,
+        )
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        if self.is_empty() {
+            other
+        } else if other.is_empty() {
+            self
+        } else {
+            Self::new(
+                Vec2I::min(self.min, other.min),
+                Vec2I::max(self.max, other.max),
+            )
+        }
+    }
+
+    // Empty rectangles collapse onto their minimum corner
+    pub fn intersect(self, other: Self) -> Self {
+        let min = Vec2I::max(self.min, other.min);
+        let max = Vec2I::max(min, Vec2I::min(self.max, other.max));
+        Self::new(min, max)
+    }
+
+    pub fn grow(self, amount: Vec2I) -> Self {
+        Self::new(self.min - amount, self.max + amount)
+    }
+
+    pub fn translate(self, offset: Vec2I) -> Self {
+        Self::new(self.min + offset, self.max + offset)
+    }
+
+    pub fn columns(&self) -> Range<usize> {
+        max(0, self.min.x) as usize..max(0, self.max.x) as usize
+    }
+
+    pub fn rows(&self) -> Range<usize> {
+        max(0, self.min.y) as usize..max(0, self.max.y) as usize
+    }
+}
@@ -0,0 +1,202 @@
+use super::Vec2F;
+use std::{fs, path::Path};
+
+// How a shape combines with the matte of the shapes before it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Blend {
+    Over,
+    Add,
+    Subtract,
+    Intersect,
+    Difference,
+}
+
+impl Blend {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "over" => Some(Blend::Over),
+            "add" => Some(Blend::Add),
+            "subtract" => Some(Blend::Subtract),
+            "intersect" => Some(Blend::Intersect),
+            "difference" => Some(Blend::Difference),
+            _ => None,
+        }
+    }
+
+    pub fn apply(self, matte: f32, coverage: f32) -> f32 {
+        let out = match self {
+            Blend::Over => coverage + matte * (1.0 - coverage),
+            Blend::Add => matte + coverage,
+            Blend::Subtract => matte - coverage,
+            Blend::Intersect => matte * coverage,
+            Blend::Difference => (matte - coverage).abs(),
+        };
+        out.clamp(0.0, 1.0)
+    }
+}
+
+// A closed outline flattened to straight edges. Feather is how
+// far in pixels the edge fades out beyond the outline.
+#[derive(Clone, Debug)]
+pub struct Shape {
+    pub outline: Vec<Vec2F>,
+    pub feather: f32,
+    pub invert: bool,
+    pub blend: Blend,
+}
+
+impl Shape {
+    // Each shape is a kind followed by points written x,y and then
+    // options, as in "polygon 0,0 10,0 10,10 feather=2 invert
+    // blend=add". Bezier shapes follow each anchor with the two
+    // control points of the curve to the next, closing at the first.
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut words = src.split_whitespace();
+        let kind = words.next().ok_or("Empty shape")?;
+        let mut points = Vec::new();
+        let mut shape = Shape {
+            outline: Vec::new(),
+            feather: 0.0,
+            invert: false,
+            blend: Blend::Over,
+        };
+        for word in words {
+            if word == "invert" {
+                shape.invert = true;
+            } else if let Some(feather) = word.strip_prefix("feather=") {
+                shape.feather = feather
+                    .parse::<f32>()
+                    .map_err(|_| format!("Invalid feather: {}", feather))?
+                    .max(0.0);
+            } else if let Some(blend) = word.strip_prefix("blend=") {
+                shape.blend =
+                    Blend::from_name(blend).ok_or(format!("Unrecognized blend: {}", blend))?;
+            } else {
+                points.push(point(word).ok_or(format!("Invalid point: {}", word))?);
+            }
+        }
+        shape.outline = match kind {
+            "polygon" => points,
+            "bezier" => flatten(&points)?,
+            kind => return Err(format!("Unrecognized shape: {}", kind)),
+        };
+        if shape.outline.len() < 3 {
+            return Err("Shapes need at least three points".to_string());
+        }
+        Ok(shape)
+    }
+
+    // How much of the pixel centered at the position the shape
+    // covers. Within a pixel of the outline this follows the
+    // distance to it, which is what antialiases the edge.
+    pub fn coverage(&self, pos: Vec2F) -> f32 {
+        let (min, max) = self.bounds();
+        if pos.x < min.x || pos.y < min.y || pos.x > max.x || pos.y > max.y {
+            return if self.invert { 1.0 } else { 0.0 };
+        }
+
+        let mut inside = false;
+        let mut distance2 = f32::MAX;
+        let mut a = self.outline[self.outline.len() - 1];
+        for b in self.outline.iter().copied() {
+            if (a.y > pos.y) != (b.y > pos.y) {
+                let x = a.x + (pos.y - a.y) / (b.y - a.y) * (b.x - a.x);
+                if pos.x < x {
+                    inside = !inside;
+                }
+            }
+            distance2 = distance2.min(segment_distance2(pos, a, b));
+            a = b;
+        }
+        let distance = distance2.sqrt();
+        let signed = if inside { distance } else { -distance };
+
+        let mut t = ((signed + self.feather + 0.5) / (self.feather + 1.0)).clamp(0.0, 1.0);
+        if self.feather > 0.0 {
+            t = t * t * (3.0 - 2.0 * t);
+        }
+        if self.invert {
+            1.0 - t
+        } else {
+            t
+        }
+    }
+
+    // Corners of the area the shape can touch, feather included
+    pub fn bounds(&self) -> (Vec2F, Vec2F) {
+        let first = self.outline[0];
+        let (min, max) = self.outline.iter().fold((first, first), |(min, max), p| {
+            (
+                Vec2F::new(min.x.min(p.x), min.y.min(p.y)),
+                Vec2F::new(max.x.max(p.x), max.y.max(p.y)),
+            )
+        });
+        let grow = self.feather + 1.0;
+        (
+            Vec2F::new(min.x - grow, min.y - grow),
+            Vec2F::new(max.x + grow, max.y + grow),
+        )
+    }
+}
+
+// Shapes separated by new lines or semicolons. Lines
+// starting with # are comments.
+pub fn parse(src: &str) -> Result<Vec<Shape>, String> {
+    src.lines()
+        .flat_map(|line| line.split(';'))
+        .map(str::trim)
+        .filter(|shape| !shape.is_empty() && !shape.starts_with('#'))
+        .map(Shape::parse)
+        .collect()
+}
+
+pub fn load(path: &Path) -> Result<Vec<Shape>, String> {
+    let src = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    parse(&src).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn point(word: &str) -> Option<Vec2F> {
+    let mut coordinates = word.split(',');
+    let x = coordinates.next()?.parse().ok()?;
+    let y = coordinates.next()?.parse().ok()?;
+    match coordinates.next() {
+        Some(_) => None,
+        None => Some(Vec2F::new(x, y)),
+    }
+}
+
+// Splits each cubic curve into enough edges that
+// none is much longer than a couple of pixels
+fn flatten(points: &[Vec2F]) -> Result<Vec<Vec2F>, String> {
+    if points.is_empty() || !points.len().is_multiple_of(3) {
+        return Err("Bezier shapes need an anchor and two control points for each curve".into());
+    }
+    let mut outline = Vec::new();
+    for (i, curve) in points.chunks(3).enumerate() {
+        let end = points[(i * 3 + 3) % points.len()];
+        let p = [curve[0], curve[1], curve[2], end];
+        let length = (p[1] - p[0]).length() + (p[2] - p[1]).length() + (p[3] - p[2]).length();
+        let steps = ((length / 2.0).ceil() as usize).clamp(1, 256);
+        for step in 0..steps {
+            let t = step as f32 / steps as f32;
+            let s = 1.0 - t;
+            let weights = [s * s * s, 3.0 * s * s * t, 3.0 * s * t * t, t * t * t];
+            let mut out = Vec2F::new(0.0, 0.0);
+            for (point, weight) in p.iter().zip(weights.iter()) {
+                out.x += point.x * weight;
+                out.y += point.y * weight;
+            }
+            outline.push(out);
+        }
+    }
+    Ok(outline)
+}
+
+fn segment_distance2(p: Vec2F, a: Vec2F, b: Vec2F) -> f32 {
+    let edge = b - a;
+    let offset = p - a;
+    let t = ((offset.x * edge.x + offset.y * edge.y) / edge.length2().max(f32::EPSILON))
+        .clamp(0.0, 1.0);
+    let nearest = Vec2F::new(a.x + edge.x * t, a.y + edge.y * t);
+    (p - nearest).length2()
+}
@@ -1,4 +1,7 @@
-use std::cmp::max;
+use std::{
+    cmp::max,
+    hash::{Hash, Hasher},
+};
 
 #[derive(Debug, Clone)]
 pub enum Value {
@@ -8,6 +11,19 @@ pub enum Value {
     Boolean(bool),
 }
 
+// Reals hash by their bits, so values that compare
+// equal like 0.0 and -0.0 may hash differently
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            Value::Text(value) => (0, value).hash(state),
+            Value::Real(value) => (1, value.to_bits()).hash(state),
+            Value::Integer(value) => (2, value).hash(state),
+            Value::Boolean(value) => (3, value).hash(state),
+        }
+    }
+}
+
 impl Value {
     pub fn as_int(&self) -> isize {
         match self {